@@ -0,0 +1,136 @@
+use std::fs::{File, OpenOptions};
+use std::io::{self, Write};
+use std::path::Path;
+use std::time::SystemTime;
+
+use serde_derive::Serialize;
+
+use crate::config::PortName;
+use crate::Port;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Action {
+    Create,
+    Destroy,
+}
+
+/// Why a link was created or destroyed
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Reason {
+    /// Made when the graph was first seen
+    Startup,
+    /// A port of the link showed up for the first time since the start
+    PortAppeared,
+    /// A port of the link went away and came back
+    DeviceReappeared,
+    /// The configuration, the profile or the runtime links changed
+    Reload,
+    /// Someone else removed a configured link
+    Enforcement,
+    /// Put back on exit
+    Exit,
+    /// Asked for with the unlink command
+    Unlink,
+    /// Not in the snapshot given to restore --prune
+    Prune,
+}
+
+/// One end of a link, with the registry ids it had in the graph
+#[derive(Debug, Serialize)]
+pub struct End<'a> {
+    pub port: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub port_id: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub node_id: Option<&'a str>,
+}
+
+impl<'a> End<'a> {
+    pub fn new(name: &'a PortName, port: Option<(&'a str, &'a Port)>) -> End<'a> {
+        End {
+            port: &name.0,
+            port_id: port.map(|(port_id, _)| port_id),
+            node_id: port.map(|(_, port)| port.node_id.0.as_str()),
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct Entry<'a> {
+    pub timestamp: String,
+    pub action: Action,
+    pub reason: Reason,
+    /// The configuration entry the link comes from
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rule: Option<String>,
+    pub src: End<'a>,
+    pub dst: End<'a>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub link_id: Option<&'a str>,
+}
+
+impl Entry<'_> {
+    pub fn now() -> String {
+        humantime::format_rfc3339_millis(SystemTime::now()).to_string()
+    }
+}
+
+/// Append-only file of JSON lines, one for each link made or removed
+#[derive(Debug)]
+pub struct AuditLog {
+    file: File,
+}
+
+impl AuditLog {
+    pub fn open(path: &Path) -> io::Result<AuditLog> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(AuditLog { file })
+    }
+
+    pub fn write(&mut self, entry: &Entry) -> io::Result<()> {
+        let line = serde_json::to_string(entry).expect("Failed to serialize json") + "\n";
+        // in one go, so that the lines of concurrent writers don't mix
+        self.file.write_all(line.as_bytes())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn entries() {
+        let path = std::env::temp_dir().join(format!("pw-connections-audit-{}.jsonl", std::process::id()));
+        let _ignore = std::fs::remove_file(&path);
+        let mut log = AuditLog::open(&path).unwrap();
+        let entry = Entry {
+            timestamp: "2024-05-02T10:14:03.512Z".to_string(),
+            action: Action::Create,
+            reason: Reason::DeviceReappeared,
+            rule: Some("profile gig".to_string()),
+            src: End {
+                port: "synth:out",
+                port_id: Some("98"),
+                node_id: Some("71"),
+            },
+            dst: End {
+                port: "speaker:in",
+                port_id: None,
+                node_id: None,
+            },
+            link_id: None,
+        };
+        log.write(&entry).unwrap();
+        log.write(&Entry { action: Action::Destroy, reason: Reason::Reload, rule: None, link_id: Some("80"), ..entry })
+            .unwrap();
+        assert_eq!(
+            std::fs::read_to_string(&path).unwrap(),
+            r#"{"timestamp":"2024-05-02T10:14:03.512Z","action":"create","reason":"device_reappeared","rule":"profile gig","src":{"port":"synth:out","port_id":"98","node_id":"71"},"dst":{"port":"speaker:in"}}
+{"timestamp":"2024-05-02T10:14:03.512Z","action":"destroy","reason":"reload","src":{"port":"synth:out","port_id":"98","node_id":"71"},"dst":{"port":"speaker:in"},"link_id":"80"}
+"#
+        );
+        std::fs::remove_file(&path).unwrap();
+    }
+}
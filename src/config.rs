@@ -1,5 +1,7 @@
 use serde_derive::{Serialize, Deserialize};
-use std::{fmt, fs, io};
+use std::collections::BTreeMap;
+use std::hash::{Hash, Hasher};
+use std::{cmp, fmt, fs, io};
 use thiserror::Error;
 
 #[derive(Error, Debug)]
@@ -15,11 +17,14 @@ pub enum Error {
     // TomlSerError(#[from] toml::ser::Error),
     #[error(transparent)]
     IOError(#[from] io::Error),
-    // #[error(transparent)]
-    // AtomicIOError(#[from] atomicwrites::Error<io::Error>),
+    #[error(transparent)]
+    AtomicIOError(#[from] atomicwrites::Error<io::Error>),
 
     #[error(transparent)]
     BraceExpansionError(#[from] BraceExpansionError),
+
+    #[error("Unknown profile {0}")]
+    UnknownProfileError(String),
 }
 
 #[derive(Error, Debug)]
@@ -41,28 +46,224 @@ impl fmt::Display for ParseError {
     }
 }
 
+/// Syntax of a configuration file
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum ConfigFormat {
+    #[default]
+    Yaml,
+    Json,
+    Toml,
+}
+
+impl ConfigFormat {
+    /// Guesses the format from the extension of the file, YAML if there is none
+    pub fn from_filename(filename: &str) -> ConfigFormat {
+        match std::path::Path::new(filename).extension().and_then(|extension| extension.to_str()) {
+            Some("json") => ConfigFormat::Json,
+            Some("toml") => ConfigFormat::Toml,
+            _ => ConfigFormat::Yaml,
+        }
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize, Clone, Eq, Hash, PartialOrd, PartialEq, Ord)]
 pub struct PortName(pub String);
 
-#[derive(Debug, Serialize, Deserialize, Clone, Eq, Hash, PartialOrd, PartialEq, Ord)]
+/// What happens to a link when the daemon exits
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum OnExit {
+    /// The link is removed along with the process
+    #[default]
+    Destroy,
+    /// The link is left in place
+    Linger,
+    /// The links that existed when the daemon started are put back
+    Restore,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
 #[serde(deny_unknown_fields)]
 pub struct NamedLink {
     pub src: PortName,
     pub dst: PortName,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub on_exit: Option<OnExit>,
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+impl NamedLink {
+    /// A link with no exit policy of its own
+    pub fn new(src: &str, dst: &str) -> NamedLink {
+        NamedLink {
+            src: PortName(src.to_string()),
+            dst: PortName(dst.to_string()),
+            on_exit: None,
+        }
+    }
+
+    // A link is identified by its ports only
+    fn key(&self) -> (&PortName, &PortName) {
+        (&self.src, &self.dst)
+    }
+}
+
+impl PartialEq for NamedLink {
+    fn eq(&self, other: &Self) -> bool {
+        self.key() == other.key()
+    }
+}
+
+impl Eq for NamedLink {}
+
+impl Hash for NamedLink {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.key().hash(state)
+    }
+}
+
+impl PartialOrd for NamedLink {
+    fn partial_cmp(&self, other: &Self) -> Option<cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for NamedLink {
+    fn cmp(&self, other: &Self) -> cmp::Ordering {
+        self.key().cmp(&other.key())
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
 pub struct NamedLinks(pub Vec<NamedLink>);
 
+/// Links that are active only while the profile is selected, in addition to the common links
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+#[serde(deny_unknown_fields)]
+pub struct Profile {
+    pub links: NamedLinks,
+}
+
+/// Action performed when a Program Change or a Control Change message arrives
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct MidiTrigger {
+    /// MIDI channel 1-16; any channel if not given
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub channel: Option<u8>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub program: Option<u8>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cc: Option<u8>,
+
+    /// Control Change value to react to; any value from 64 up if not given
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub value: Option<u8>,
+
+    /// Switch to this profile
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub profile: Option<String>,
+
+    /// Add this link if it isn't desired, otherwise remove it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub toggle: Option<NamedLink>,
+}
+
+fn default_midi_name() -> String {
+    "pw-connections".to_string()
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct Midi {
+    /// Node name of the MIDI input; its port is <name>:input
+    #[serde(default = "default_midi_name")]
+    pub name: String,
+
+    #[serde(default)]
+    pub triggers: Vec<MidiTrigger>,
+}
+
+/// Graph events that can run a hook
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum HookEvent {
+    /// A configured link appeared in the graph
+    LinkCreated,
+    /// A configured link went away
+    LinkLost,
+    /// A configured link can't be made, a port is missing
+    LinkUnresolved,
+    /// A node appeared in the graph
+    DeviceAppeared,
+}
+
+impl HookEvent {
+    pub fn name(&self) -> &'static str {
+        match self {
+            HookEvent::LinkCreated => "link_created",
+            HookEvent::LinkLost => "link_lost",
+            HookEvent::LinkUnresolved => "link_unresolved",
+            HookEvent::DeviceAppeared => "device_appeared",
+        }
+    }
+}
+
+/// Shell command run when an event happens
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct Hook {
+    pub on: HookEvent,
+
+    /// Run with sh -c
+    pub command: String,
+
+    /// For the link events, only the links from this port
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub src: Option<PortName>,
+
+    /// For the link events, only the links to this port
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dst: Option<PortName>,
+
+    /// For device_appeared, only this node, by name or description
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub node: Option<String>,
+}
+
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Config {
     pub links: NamedLinks,
+
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub profiles: BTreeMap<String, Profile>,
+
+    /// Profile selected at startup
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub profile: Option<String>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub midi: Option<Midi>,
+
+    /// Exit policy of the links that don't have their own
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub on_exit: Option<OnExit>,
+
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub hooks: Vec<Hook>,
 }
 
 impl Default for Config {
     fn default() -> Self {
         Config {
             links: NamedLinks(vec![]),
+            profiles: BTreeMap::new(),
+            profile: None,
+            midi: None,
+            on_exit: None,
+            hooks: vec![],
         }
     }
 }
@@ -213,7 +414,7 @@ fn brace_expansion(str: &str) -> Result<Vec<String>, BraceExpansionError>
     }
 }
 
-fn expand_links(links: NamedLinks) -> Result<NamedLinks, Error> {
+pub fn expand_links(links: NamedLinks) -> Result<NamedLinks, Error> {
     let mut new_links = NamedLinks::default();
     for link in links.0.iter() {
 	let src_expansions = brace_expansion(&link.src.0)?;
@@ -227,36 +428,252 @@ fn expand_links(links: NamedLinks) -> Result<NamedLinks, Error> {
 	for (src, dst) in std::iter::zip(src_expansions.iter(),
 					 dst_expansions.iter()) {
 	    new_links.0.push(NamedLink {src: PortName(src.clone()),
-					dst: PortName(dst.clone())});
+					dst: PortName(dst.clone()),
+					on_exit: link.on_exit});
 	}
     }
     Ok(new_links)
 }
 
+fn char_class(ch: Option<char>) -> Option<bool> {
+    match ch {
+	Some(ch) if ch.is_ascii_digit() => Some(true),
+	Some(ch) if ch.is_alphabetic() => Some(false),
+	_ => None,
+    }
+}
+
+// Whether splitting the string at the position would cut a word or a number
+fn splits_word(str: &str, position: usize) -> bool {
+    let before = char_class(str[..position].chars().next_back());
+    before.is_some() && before == char_class(str[position..].chars().next())
+}
+
+// Splits the strings into a common prefix, the varying parts and a common suffix.
+// Words and numbers are never split, so that the varying parts can become ranges.
+fn split_common<'a>(strs: &[&'a str]) -> (&'a str, Vec<&'a str>, &'a str) {
+    let first = strs[0];
+    let mut prefix_len = first.len();
+    let mut suffix_len = first.len();
+    for str in &strs[1..] {
+	prefix_len = first.bytes().zip(str.bytes()).take(prefix_len).take_while(|(a, b)| a == b).count();
+	suffix_len = first.bytes().rev().zip(str.bytes().rev()).take(suffix_len).take_while(|(a, b)| a == b).count();
+    }
+    let min_len = strs.iter().map(|str| str.len()).min().unwrap_or(0);
+    suffix_len = suffix_len.min(min_len - prefix_len.min(min_len));
+    while !first.is_char_boundary(prefix_len) || strs.iter().any(|str| splits_word(str, prefix_len)) {
+	prefix_len -= 1;
+    }
+    while !first.is_char_boundary(first.len() - suffix_len)
+	|| strs.iter().any(|str| splits_word(str, str.len() - suffix_len)) {
+	suffix_len -= 1;
+    }
+    let varying = strs.iter().map(|str| &str[prefix_len..str.len() - suffix_len]).collect();
+    (&first[..prefix_len], varying, &first[first.len() - suffix_len..])
+}
+
+// The varying parts as a range if they are consecutive numbers, otherwise as a list
+fn brace(prefix: &str, varying: &[&str], suffix: &str) -> String {
+    let numbers: Option<Vec<i64>> = varying.iter()
+	.map(|str| match str.parse() {
+	    Ok(number) if is_numeric_string(str) && format!("{number}") == *str => Some(number),
+	    _ => None,
+	})
+	.collect();
+    match numbers {
+	Some(numbers) if numbers.windows(2).all(|pair| pair[1] == pair[0] + 1) =>
+	    format!("{prefix}{{{}..{}}}{suffix}", numbers[0], numbers[numbers.len() - 1]),
+	_ => format!("{prefix}{{{}}}{suffix}", varying.join(",")),
+    }
+}
+
+// Folds the links into one link with braces, if brace_expansion would give them back
+fn compress_group(group: &[&NamedLink]) -> Option<NamedLink> {
+    let srcs: Vec<&str> = group.iter().map(|link| link.src.0.as_str()).collect();
+    let dsts: Vec<&str> = group.iter().map(|link| link.dst.0.as_str()).collect();
+    let (src_prefix, src_varying, src_suffix) = split_common(&srcs);
+    let (dst_prefix, dst_varying, dst_suffix) = split_common(&dsts);
+    // a brace can't span the node and port names, that would be hard to read anyway
+    let bad = |varying: &[&str]| varying.iter().any(|str| str.contains([':', '{', '}', ',']))
+	|| (1..varying.len()).any(|index| varying[..index].contains(&varying[index]));
+    if bad(&src_varying) || bad(&dst_varying) {
+	return None;
+    }
+    let link = NamedLink {
+	src: PortName(brace(src_prefix, &src_varying, src_suffix)),
+	dst: PortName(brace(dst_prefix, &dst_varying, dst_suffix)),
+	on_exit: group[0].on_exit,
+    };
+    let expanded = expand_links(NamedLinks(vec![link.clone()])).ok()?;
+    let same = expanded.0.len() == group.len()
+	&& std::iter::zip(&expanded.0, group).all(|(a, b)| a.src == b.src && a.dst == b.dst);
+    same.then_some(link)
+}
+
+fn numeric_key(str: &str) -> (usize, &str) {
+    // sorts "10" after "9"
+    (str.len(), str)
+}
+
+/// The inverse of expand_links: folds links that differ only in one part of
+/// their names back into {a,b} or {1..3} braces
+pub fn compress_links(links: &NamedLinks) -> NamedLinks {
+    let mut used = vec![false; links.0.len()];
+    let mut result = NamedLinks::default();
+    for index in 0..links.0.len() {
+	if used[index] {
+	    continue;
+	}
+	used[index] = true;
+	let mut group = vec![&links.0[index]];
+	for (other_index, other) in links.0.iter().enumerate().skip(index + 1) {
+	    if used[other_index] || other.on_exit != links.0[index].on_exit {
+		continue;
+	    }
+	    let mut candidate = group.clone();
+	    candidate.push(other);
+	    let srcs: Vec<&str> = candidate.iter().map(|link| link.src.0.as_str()).collect();
+	    let (_, varying, _) = split_common(&srcs);
+	    let mut order: Vec<usize> = (0..candidate.len()).collect();
+	    order.sort_by_key(|&position| numeric_key(varying[position]));
+	    let candidate: Vec<&NamedLink> = order.iter().map(|&position| candidate[position]).collect();
+	    if compress_group(&candidate).is_some() {
+		group = candidate;
+		used[other_index] = true;
+	    }
+	}
+	match compress_group(&group) {
+	    Some(link) if group.len() > 1 => result.0.push(link),
+	    _ => result.0.extend(group.into_iter().cloned()),
+	}
+    }
+    result
+}
+
+/// Writes to a temporary file and moves it over the file, so that it is never half written
+pub fn write_file(filename: &str, contents: &str) -> Result<(), Error> {
+    let file = atomicwrites::AtomicFile::new(filename, atomicwrites::AllowOverwrite);
+    file.write(|file| io::Write::write_all(file, contents.as_bytes()))?;
+    Ok(())
+}
+
 impl Config {
     // If no file is found, returns default config instead of error
-    pub fn load(filename: &str) -> Result<Config, Error> {
+    // The format is guessed from the file name if not given
+    pub fn load(filename: &str, format: Option<ConfigFormat>) -> Result<Config, Error> {
         let contents = match fs::read_to_string(filename) {
             Ok(contents) => contents,
             // Err(error) if error.kind() == io::ErrorKind::NotFound => return Ok(Config::default()),
             Err(error) => return Err(Error::IOError(error)),
         };
-        let mut config: Config = match serde_yaml::from_str(&contents) {
-            Ok(contents) => contents,
-            Err(error) if error.location().is_some() => {
-                return Err(Error::ParseError(ParseError {
-                    filename: String::from(filename),
-                    message: format!("{}", error),
-                }));
-            }
-            Err(error) => return Err(Error::YamlError(error)),
-        };
+        let format = format.unwrap_or_else(|| ConfigFormat::from_filename(filename));
+        Config::parse(&contents, filename, format)
+    }
+
+    fn parse(contents: &str, filename: &str, format: ConfigFormat) -> Result<Config, Error> {
+        let mut config = Config::deserialize(contents, filename, format)?;
 	config.links = expand_links(config.links)?;
+	for profile in config.profiles.values_mut() {
+	    profile.links = expand_links(std::mem::take(&mut profile.links))?;
+	}
+	if let Some(profile) = &config.profile {
+	    config.profile_links(Some(profile))?;
+	}
+	config.check_midi_triggers(filename)?;
+	config.check_hooks(filename)?;
         Ok(config)
     }
 
-    pub fn dump(&self) {
-	println!("{}", serde_yaml::to_string(&self).expect("Failed to serialize yaml"));
+    /// Reads a config as written, without brace expansion or checks
+    pub fn deserialize(contents: &str, filename: &str, format: ConfigFormat) -> Result<Config, Error> {
+        let parse_error = |message: String| {
+            Error::ParseError(ParseError {
+                filename: String::from(filename),
+                message,
+            })
+        };
+        let config = match format {
+            ConfigFormat::Yaml => match serde_yaml::from_str(contents) {
+                Ok(contents) => contents,
+                Err(error) if error.location().is_some() => return Err(parse_error(format!("{}", error))),
+                Err(error) => return Err(Error::YamlError(error)),
+            },
+            ConfigFormat::Json => serde_json::from_str(contents).map_err(|error| parse_error(format!("{}", error)))?,
+            ConfigFormat::Toml => toml::from_str(contents).map_err(|error| parse_error(format!("{}", error)))?,
+        };
+        Ok(config)
+    }
+
+    fn check_midi_triggers(&self, filename: &str) -> Result<(), Error> {
+	let triggers = self.midi.iter().flat_map(|midi| midi.triggers.iter());
+	for (index, trigger) in triggers.enumerate() {
+	    let message = if trigger.program.is_some() == trigger.cc.is_some() {
+		"must have either program or cc"
+	    } else if trigger.profile.is_some() == trigger.toggle.is_some() {
+		"must have either profile or toggle"
+	    } else if trigger.value.is_some() && trigger.cc.is_none() {
+		"value can only be used with cc"
+	    } else if trigger.channel.is_some_and(|channel| !(1..=16).contains(&channel)) {
+		"channel must be between 1 and 16"
+	    } else if let Some(Err(error)) = trigger.profile.as_deref().map(|profile| self.profile_links(Some(profile))) {
+		return Err(error);
+	    } else {
+		continue;
+	    };
+	    return Err(Error::ParseError(ParseError {
+		filename: String::from(filename),
+		message: format!("MIDI trigger {index} {message}"),
+	    }));
+	}
+	Ok(())
+    }
+
+    fn check_hooks(&self, filename: &str) -> Result<(), Error> {
+	for (index, hook) in self.hooks.iter().enumerate() {
+	    let device = hook.on == HookEvent::DeviceAppeared;
+	    let message = if device && (hook.src.is_some() || hook.dst.is_some()) {
+		"can only have src and dst for link events"
+	    } else if !device && hook.node.is_some() {
+		"can only have node for device_appeared"
+	    } else {
+		continue;
+	    };
+	    return Err(Error::ParseError(ParseError {
+		filename: String::from(filename),
+		message: format!("Hook {index} {message}"),
+	    }));
+	}
+	Ok(())
+    }
+
+    // The common links followed by the links of the given profile
+    pub fn profile_links(&self, profile: Option<&str>) -> Result<NamedLinks, Error> {
+	let mut links = self.links.clone();
+	if let Some(profile) = profile {
+	    match self.profiles.get(profile) {
+		Some(profile) => links.0.extend(profile.links.0.iter().cloned()),
+		None => return Err(Error::UnknownProfileError(profile.to_string())),
+	    }
+	}
+	Ok(links)
+    }
+
+    // The policy of the link's own entry in the common or profile links, or the global one
+    pub fn on_exit(&self, link: &NamedLink) -> OnExit {
+	let mut links = self.links.0.iter()
+	    .chain(self.profiles.values().flat_map(|profile| profile.links.0.iter()));
+	links.find(|configured| *configured == link && configured.on_exit.is_some())
+	    .and_then(|configured| configured.on_exit)
+	    .or(self.on_exit)
+	    .unwrap_or_default()
+    }
+
+    pub fn dump(&self, format: ConfigFormat) -> String {
+	match format {
+	    ConfigFormat::Yaml => format!("{}\n", serde_yaml::to_string(&self).expect("Failed to serialize yaml")),
+	    ConfigFormat::Json => format!("{}\n", serde_json::to_string_pretty(&self).expect("Failed to serialize json")),
+	    ConfigFormat::Toml => toml::to_string(&self).expect("Failed to serialize toml"),
+	}
     }
 }
 
@@ -351,4 +768,114 @@ mod tests {
 		       message: "Range must be numeric".to_string()
 		   }));
     }
+
+    #[test]
+    fn compression() {
+        let mut channels: Vec<_> = (1..=64).map(|channel| (format!("iface:capture_AUX{}", channel - 1), format!("mixer:in_{channel}"))).collect();
+        channels.sort();
+        let channels = NamedLinks(channels.iter().map(|(src, dst)| NamedLink::new(src, dst)).collect());
+        assert_eq!(compress_links(&channels).0, vec![NamedLink::new("iface:capture_AUX{0..63}", "mixer:in_{1..64}")]);
+
+        let mixed = NamedLinks(vec![
+            NamedLink::new("ALC257 Analog:capture_FL", "Firefox:input_FL"),
+            NamedLink::new("ALC257 Analog:capture_FR", "Firefox:input_FR"),
+            NamedLink::new("Midi Through:(capture_0) Midi Through Port-0", "synth:midi_in"),
+            NamedLink::new("Virtual Raw MIDI 4-1 4:(capture_0) VirMIDI 4-1", "sl:out_1"),
+            NamedLink::new("Virtual Raw MIDI 4-2 4:(capture_0) VirMIDI 4-2", "sl:out_2"),
+            NamedLink::new("synth:out_3", "sl:in"),
+            NamedLink::new("synth:out_5", "sl:in"),
+            NamedLink::new("x:out_1", "y:left"),
+            NamedLink::new("x:out_2", "y:right"),
+        ]);
+        let compressed = compress_links(&mixed);
+        assert_eq!(compressed.0, vec![
+            NamedLink::new("ALC257 Analog:capture_{FL,FR}", "Firefox:input_{FL,FR}"),
+            NamedLink::new("Midi Through:(capture_0) Midi Through Port-0", "synth:midi_in"),
+            NamedLink::new("Virtual Raw MIDI 4-1 4:(capture_0) VirMIDI 4-1", "sl:out_1"),
+            NamedLink::new("Virtual Raw MIDI 4-2 4:(capture_0) VirMIDI 4-2", "sl:out_2"),
+            NamedLink::new("synth:out_3", "sl:in"),
+            NamedLink::new("synth:out_5", "sl:in"),
+            NamedLink::new("x:out_{1..2}", "y:{left,right}"),
+        ]);
+        assert_eq!(expand_links(compressed).unwrap().0, mixed.0);
+    }
+
+    #[test]
+    fn on_exit() {
+        let mut config: Config = serde_yaml::from_str(r#"
+links:
+  - {src: "a:out_{1..2}", dst: "b:in_{1..2}", on_exit: linger}
+  - {src: "a:out_3", dst: "b:in_3"}
+on_exit: restore
+"#).unwrap();
+        config.links = expand_links(config.links).unwrap();
+        assert_eq!(config.on_exit(&NamedLink::new("a:out_2", "b:in_2")), OnExit::Linger);
+        assert_eq!(config.on_exit(&NamedLink::new("a:out_3", "b:in_3")), OnExit::Restore);
+        config.on_exit = None;
+        assert_eq!(config.on_exit(&NamedLink::new("c:out", "d:in")), OnExit::Destroy);
+    }
+
+    #[test]
+    fn on_exit_of_initial_links() {
+        let config: Config = serde_yaml::from_str(r#"
+links:
+  - {src: "a:out", dst: "b:in", on_exit: linger}
+profiles:
+  gig:
+    links:
+      - {src: "c:out", dst: "d:in", on_exit: restore}
+on_exit: destroy
+"#).unwrap();
+        // found in the graph at start, not configured
+        assert_eq!(config.on_exit(&NamedLink::new("x:out", "y:in")), OnExit::Destroy);
+        // configured in a profile that isn't active
+        assert_eq!(config.on_exit(&NamedLink::new("c:out", "d:in")), OnExit::Restore);
+        assert_eq!(config.on_exit(&NamedLink::new("a:out", "b:in")), OnExit::Linger);
+    }
+
+    #[test]
+    fn formats() {
+        assert_eq!(ConfigFormat::from_filename("studio.json"), ConfigFormat::Json);
+        assert_eq!(ConfigFormat::from_filename("/etc/studio.toml"), ConfigFormat::Toml);
+        assert_eq!(ConfigFormat::from_filename("studio"), ConfigFormat::Yaml);
+
+        let json = r#"{"links": [{"src": "a:out_{1..2}", "dst": "b:in_{1..2}"}], "on_exit": "linger"}"#;
+        let toml = r#"
+on_exit = "linger"
+
+[[links]]
+src = "a:out_{1..2}"
+dst = "b:in_{1..2}"
+"#;
+        let yaml = Config::parse(json, "studio.json", ConfigFormat::Yaml).unwrap();
+        for (contents, format) in [(json, ConfigFormat::Json), (toml, ConfigFormat::Toml)] {
+            let config = Config::parse(contents, "studio", format).unwrap();
+            assert_eq!(config.links, yaml.links);
+            assert_eq!(config.on_exit, Some(OnExit::Linger));
+            let dumped = Config::parse(&config.dump(format), "studio", format).unwrap();
+            assert_eq!(dumped.links, config.links);
+        }
+        let filename = std::env::temp_dir().join(format!("pw-connections-{}.toml", std::process::id()));
+        let filename = filename.to_str().unwrap();
+        write_file(filename, &yaml.dump(ConfigFormat::Toml)).unwrap();
+        let saved = Config::load(filename, None);
+        fs::remove_file(filename).unwrap();
+        assert_eq!(saved.unwrap().links, yaml.links);
+
+        assert!(matches!(
+            Config::parse("{\"links\": [}", "studio.json", ConfigFormat::Json),
+            Err(Error::ParseError(ParseError { filename, .. })) if filename == "studio.json"
+        ));
+    }
+
+    #[test]
+    fn hooks() {
+        let yaml = "links: []\nhooks:\n  - {on: link_created, src: a:out, command: 'true'}\n";
+        assert_eq!(Config::parse(yaml, "hooks.yaml", ConfigFormat::Yaml).unwrap().hooks[0].on, HookEvent::LinkCreated);
+        let yaml = "links: []\nhooks:\n  - {on: device_appeared, src: a:out, command: 'true'}\n";
+        assert!(matches!(
+            Config::parse(yaml, "hooks.yaml", ConfigFormat::Yaml),
+            Err(Error::ParseError(ParseError { message, .. })) if message == "Hook 0 can only have src and dst for link events"
+        ));
+    }
 }
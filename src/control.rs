@@ -0,0 +1,123 @@
+use serde_derive::{Deserialize, Serialize};
+use std::io::{self, Read, Write};
+use std::net::Shutdown;
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+use tracing::warn;
+
+use crate::config::NamedLink;
+use crate::error;
+use crate::status::StatusEntry;
+use crate::Main;
+
+/// Request sent to a running daemon over the control socket
+#[derive(Debug, Serialize, Deserialize)]
+pub enum Request {
+    Status,
+    AddLink(NamedLink),
+    RemoveLink(NamedLink),
+    /// Like AddLink and RemoveLink, for port names taken as they are, braces and all
+    AddExactLink(NamedLink),
+    RemoveExactLink(NamedLink),
+    SetProfile(Option<String>),
+    Reload,
+    Save,
+}
+
+/// Response to a control socket request
+#[derive(Debug, Serialize, Deserialize)]
+pub enum Response {
+    Ok,
+    Status(Vec<StatusEntry>),
+    Error(String),
+}
+
+// How long a client may take to send its request
+const READ_TIMEOUT: Duration = Duration::from_secs(5);
+
+pub fn default_socket_path() -> PathBuf {
+    let dir = match std::env::var_os("XDG_RUNTIME_DIR") {
+        Some(dir) => PathBuf::from(dir),
+        None => std::env::temp_dir(),
+    };
+    dir.join("pw-connections.sock")
+}
+
+/// Listens to the control socket; the socket file is removed when this is dropped
+pub struct Server {
+    path: PathBuf,
+}
+
+impl Drop for Server {
+    fn drop(&mut self) {
+        let _ignore = std::fs::remove_file(&self.path);
+    }
+}
+
+impl Server {
+    pub fn start(path: &Path, main: Arc<Mutex<Main>>) -> io::Result<Server> {
+        if path.exists() {
+            if UnixStream::connect(path).is_ok() {
+                return Err(io::Error::new(
+                    io::ErrorKind::AddrInUse,
+                    format!("{} is in use by another instance", path.display()),
+                ));
+            }
+            // stale socket from a previous run
+            std::fs::remove_file(path)?;
+        }
+        let listener = UnixListener::bind(path)?;
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                match stream {
+                    Ok(stream) => {
+                        // on its own thread, so that a slow client doesn't hold up the others
+                        let main = main.clone();
+                        thread::spawn(move || {
+                            if let Err(error) = handle_client(stream, &main) {
+                                warn!(%error, "Control socket client error");
+                            }
+                        });
+                    }
+                    Err(error) => warn!(%error, "Control socket error"),
+                }
+            }
+        });
+        Ok(Server {
+            path: path.to_path_buf(),
+        })
+    }
+}
+
+fn handle_client(mut stream: UnixStream, main: &Mutex<Main>) -> Result<(), error::Error> {
+    stream.set_read_timeout(Some(READ_TIMEOUT))?;
+    let mut contents = String::new();
+    stream.read_to_string(&mut contents)?;
+    let response = match serde_yaml::from_str::<Request>(&contents) {
+        Ok(request) => {
+            let mut main = main.lock().expect("Failed to lock main");
+            main.handle_request(request)
+        }
+        Err(error) => Response::Error(format!("Invalid request: {error}")),
+    };
+    stream.write_all(serde_yaml::to_string(&response)?.as_bytes())?;
+    Ok(())
+}
+
+/// Sends a request to a running daemon and waits for its response
+pub fn request(path: &Path, request: &Request) -> Result<Response, error::Error> {
+    let mut stream = UnixStream::connect(path).map_err(|error| {
+        error::Error::ControlError(format!(
+            "Cannot connect to {}, is the daemon running? ({error})",
+            path.display()
+        ))
+    })?;
+    stream.write_all(serde_yaml::to_string(request)?.as_bytes())?;
+    stream.shutdown(Shutdown::Write)?;
+    let mut contents = String::new();
+    stream.read_to_string(&mut contents)?;
+    Ok(serde_yaml::from_str(&contents)?)
+}
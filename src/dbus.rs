@@ -0,0 +1,171 @@
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use tracing::warn;
+use zbus::blocking::{connection, Connection};
+use zbus::fdo;
+
+use crate::config::NamedLink;
+use crate::control::{Request, Response};
+use crate::events::Event;
+use crate::Main;
+
+pub const DEFAULT_NAME: &str = "fi.vincit.PwConnections";
+const PATH: &str = "/fi/vincit/PwConnections";
+
+/// src, dst, state, seconds in state, relinks
+type StatusRow = (String, String, String, u64, u32);
+
+struct Service {
+    main: Arc<Mutex<Main>>,
+}
+
+impl Service {
+    fn request(&self, request: Request) -> fdo::Result<Response> {
+        let mut main = self.main.lock().expect("Failed to lock main");
+        match main.handle_request(request) {
+            Response::Error(error) => Err(fdo::Error::Failed(error)),
+            response => Ok(response),
+        }
+    }
+}
+
+#[zbus::interface(name = "fi.vincit.PwConnections")]
+impl Service {
+    fn add_link(&self, src: String, dst: String) -> fdo::Result<()> {
+        self.request(Request::AddLink(NamedLink::new(&src, &dst)))?;
+        Ok(())
+    }
+
+    fn remove_link(&self, src: String, dst: String) -> fdo::Result<()> {
+        self.request(Request::RemoveLink(NamedLink::new(&src, &dst)))?;
+        Ok(())
+    }
+
+    /// An empty name selects no profile
+    fn set_profile(&self, profile: String) -> fdo::Result<()> {
+        let profile = if profile.is_empty() { None } else { Some(profile) };
+        self.request(Request::SetProfile(profile))?;
+        Ok(())
+    }
+
+    fn reload(&self) -> fdo::Result<()> {
+        self.request(Request::Reload)?;
+        Ok(())
+    }
+
+    fn save(&self) -> fdo::Result<()> {
+        self.request(Request::Save)?;
+        Ok(())
+    }
+
+    fn status(&self) -> fdo::Result<Vec<StatusRow>> {
+        match self.request(Request::Status)? {
+            Response::Status(entries) => Ok(entries
+                .into_iter()
+                .map(|entry| {
+                    (
+                        entry.link.src.0,
+                        entry.link.dst.0,
+                        entry.state.to_string(),
+                        entry.duration_secs,
+                        entry.relinks,
+                    )
+                })
+                .collect()),
+            response => Err(fdo::Error::Failed(format!("Unexpected response {response:?}"))),
+        }
+    }
+
+    #[zbus(signal)]
+    async fn link_created(ctxt: &zbus::SignalContext<'_>, src: &str, dst: &str) -> zbus::Result<()>;
+
+    #[zbus(signal)]
+    async fn link_lost(ctxt: &zbus::SignalContext<'_>, src: &str, dst: &str) -> zbus::Result<()>;
+
+    #[zbus(signal)]
+    async fn port_appeared(ctxt: &zbus::SignalContext<'_>, port: &str) -> zbus::Result<()>;
+
+    #[zbus(signal)]
+    async fn port_removed(ctxt: &zbus::SignalContext<'_>, port: &str) -> zbus::Result<()>;
+}
+
+/// Claims the bus name and serves the interface until the returned connection is dropped.
+/// Without an address the session bus is used.
+pub fn start(name: &str, address: Option<&str>, main: Arc<Mutex<Main>>) -> zbus::Result<Connection> {
+    let builder = match address {
+        Some(address) => connection::Builder::address(address)?,
+        None => connection::Builder::session()?,
+    };
+    let events = main.lock().expect("Failed to lock main").subscribers.subscribe();
+    let connection = builder
+        .name(name.to_string())?
+        .serve_at(PATH, Service { main })?
+        .build()?;
+
+    let interface = connection.object_server().interface::<_, Service>(PATH)?;
+    thread::spawn(move || {
+        for event in events {
+            let ctxt = interface.signal_context();
+            let result = match &event {
+                Event::PortAppeared { port } => zbus::block_on(Service::port_appeared(ctxt, &port.0)),
+                Event::PortRemoved { port } => zbus::block_on(Service::port_removed(ctxt, &port.0)),
+                Event::LinkCreated { src, dst } => {
+                    zbus::block_on(Service::link_created(ctxt, &src.0, &dst.0))
+                }
+                Event::LinkLost { src, dst } => zbus::block_on(Service::link_lost(ctxt, &src.0, &dst.0)),
+            };
+            if let Err(error) = result {
+                warn!(?event, %error, "Failed to send D-Bus signal");
+            }
+        }
+    });
+    Ok(connection)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config;
+    use std::io::{BufRead, BufReader};
+    use std::process::{Command, Stdio};
+
+    #[test]
+    #[ignore = "needs dbus-daemon"]
+    fn private_bus() {
+        let mut daemon = Command::new("dbus-daemon")
+            .args(["--session", "--nofork", "--print-address"])
+            .stdout(Stdio::piped())
+            .spawn()
+            .expect("Failed to start dbus-daemon");
+        let mut address = String::new();
+        BufReader::new(daemon.stdout.as_mut().unwrap())
+            .read_line(&mut address)
+            .unwrap();
+        let address = address.trim();
+
+        let main = Arc::new(Mutex::new(Main::new(config::Config::default(), None, None, crate::Mode::Daemon)));
+        let _service = start(DEFAULT_NAME, Some(address), main.clone()).unwrap();
+
+        let client = connection::Builder::address(address).unwrap().build().unwrap();
+        client
+            .call_method(Some(DEFAULT_NAME), PATH, Some(DEFAULT_NAME), "AddLink", &("a:out_{1..2}", "b:in_{1..2}"))
+            .unwrap();
+        let status: Vec<StatusRow> = client
+            .call_method(Some(DEFAULT_NAME), PATH, Some(DEFAULT_NAME), "Status", &())
+            .unwrap()
+            .body()
+            .deserialize()
+            .unwrap();
+        assert_eq!(
+            status.iter().map(|row| (row.0.as_str(), row.1.as_str(), row.2.as_str())).collect::<Vec<_>>(),
+            vec![("a:out_1", "b:in_1", "pending"), ("a:out_2", "b:in_2", "pending")]
+        );
+        assert!(client
+            .call_method(Some(DEFAULT_NAME), PATH, Some(DEFAULT_NAME), "SetProfile", &("nonexistent",))
+            .is_err());
+
+        daemon.kill().unwrap();
+        let _ignore = daemon.wait();
+    }
+}
@@ -0,0 +1,80 @@
+use crate::config::NamedLink;
+
+/// Differences between the configured links and the links of the graph
+#[derive(Debug, Default)]
+pub struct Diff {
+    /// Configured, both ports exist, but not linked
+    pub missing: Vec<NamedLink>,
+    /// Linked, but not configured
+    pub unconfigured: Vec<NamedLink>,
+    /// Configured, but a port is absent
+    pub impossible: Vec<NamedLink>,
+}
+
+// One hunk with the links in the YAML shape of --dump, each line prefixed with the marker
+fn hunk(output: &mut String, title: &str, marker: char, links: &[NamedLink]) {
+    if links.is_empty() {
+        return;
+    }
+    output.push_str(&format!("@@ {title} @@\n"));
+    let links: Vec<_> = links
+        .iter()
+        .map(|link| NamedLink {
+            on_exit: None,
+            ..link.clone()
+        })
+        .collect();
+    let yaml = serde_yaml::to_string(&links).expect("Failed to serialize yaml");
+    for line in yaml.lines() {
+        output.push(marker);
+        output.push_str(line);
+        output.push('\n');
+    }
+}
+
+impl Diff {
+    pub fn is_empty(&self) -> bool {
+        self.missing.is_empty() && self.unconfigured.is_empty() && self.impossible.is_empty()
+    }
+
+    /// Formats the differences like a unified diff from the config to the graph
+    pub fn unified(&self, config_name: &str) -> String {
+        if self.is_empty() {
+            return String::new();
+        }
+        let mut output = format!("--- {config_name}\n+++ live graph\n");
+        hunk(&mut output, "desired but missing", '-', &self.missing);
+        hunk(&mut output, "present but not configured", '+', &self.unconfigured);
+        hunk(&mut output, "configured but impossible, ports absent", '-', &self.impossible);
+        output
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unified() {
+        assert_eq!(Diff::default().unified("config.yaml"), "");
+        let diff = Diff {
+            missing: vec![NamedLink::new("a:out", "b:in")],
+            unconfigured: vec![NamedLink::new("c:out", "d:in"), NamedLink::new("c:out", "e:in")],
+            impossible: vec![],
+        };
+        assert_eq!(
+            diff.unified("config.yaml"),
+            "--- config.yaml
++++ live graph
+@@ desired but missing @@
+-- src: a:out
+-  dst: b:in
+@@ present but not configured @@
++- src: c:out
++  dst: d:in
++- src: c:out
++  dst: e:in
+"
+        );
+    }
+}
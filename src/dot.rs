@@ -0,0 +1,200 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Write;
+
+use crate::config::PortName;
+use crate::dump::{Filter, GraphLink};
+use crate::{Node, PortDirection};
+
+/// A port of the graph
+#[derive(Debug)]
+pub struct DotPort<'a> {
+    pub node: Option<&'a Node>,
+    pub port_name: &'a PortName,
+    /// The name of the port within its node
+    pub name: &'a str,
+    pub direction: PortDirection,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Edge {
+    /// Present and configured
+    Managed,
+    /// Present but not configured
+    Unmanaged,
+    /// Configured but not present
+    Missing,
+}
+
+/// A node with its ports
+struct Cluster<'a> {
+    node: Option<&'a Node>,
+    inputs: Vec<&'a DotPort<'a>>,
+    outputs: Vec<&'a DotPort<'a>>,
+}
+
+fn escape(str: &str) -> String {
+    let mut escaped = String::new();
+    for c in str.chars() {
+        if "\\\"{}|<>".contains(c) {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+fn node_label(node: Option<&Node>) -> String {
+    match node {
+        Some(Node {
+            description: Some(description),
+            ..
+        }) => description.clone(),
+        Some(node) => node.name.clone(),
+        None => "Unknown node".to_string(),
+    }
+}
+
+/// Renders the graph for Graphviz: nodes as clusters with a record of input ports and one of
+/// output ports, links as edges
+pub fn dot(ports: &[DotPort], links: &[(GraphLink, Edge)], filter: &Filter) -> String {
+    let links: Vec<_> = links.iter().filter(|(link, _)| filter.matches(link)).collect();
+    let filtered = !(filter.nodes.is_empty() && filter.ports.is_empty() && filter.regex.is_none());
+
+    let mut nodes: BTreeMap<Option<&str>, Cluster> = BTreeMap::new();
+    for port in ports {
+        let cluster = nodes
+            .entry(port.node.map(|node| node.name.as_str()))
+            .or_insert(Cluster {
+                node: port.node,
+                inputs: vec![],
+                outputs: vec![],
+            });
+        match port.direction {
+            PortDirection::In => cluster.inputs.push(port),
+            PortDirection::Out => cluster.outputs.push(port),
+        }
+    }
+    if filtered {
+        // only the nodes with a link left
+        let linked = |node: Option<&str>| {
+            links.iter().any(|(link, _)| {
+                [link.src_node, link.dst_node]
+                    .iter()
+                    .any(|end| end.map(|end| end.name.as_str()) == node)
+            })
+        };
+        nodes.retain(|node, _| linked(*node));
+    }
+
+    let mut output = String::from("digraph pipewire {\n  rankdir=LR;\n  node [shape=record];\n");
+    let mut fields: HashMap<(&PortName, &PortDirection), String> = HashMap::new();
+    for (index, cluster) in nodes.into_values().enumerate() {
+        writeln!(output, "  subgraph cluster_{index} {{").unwrap();
+        writeln!(output, "    label=\"{}\";", node_label(cluster.node).replace('\\', "\\\\").replace('"', "\\\"")).unwrap();
+        for (mut ports, suffix) in [(cluster.inputs, "in"), (cluster.outputs, "out")] {
+            if ports.is_empty() {
+                continue;
+            }
+            ports.sort_by_key(|port| port.name);
+            let record = format!("n{index}_{suffix}");
+            let label: Vec<String> = ports
+                .iter()
+                .enumerate()
+                .map(|(field, port)| {
+                    fields.insert((port.port_name, &port.direction), format!("{record}:p{field}"));
+                    format!("<p{field}> {}", escape(port.name))
+                })
+                .collect();
+            writeln!(output, "    {record} [label=\"{{{}}}\"];", label.join("|")).unwrap();
+        }
+        output.push_str("  }\n");
+    }
+
+    let mut edges: Vec<String> = links
+        .iter()
+        .filter_map(|(link, edge)| {
+            let src = fields.get(&(&link.link.src, &PortDirection::Out))?;
+            let dst = fields.get(&(&link.link.dst, &PortDirection::In))?;
+            let style = match edge {
+                Edge::Managed => " [penwidth=2]",
+                Edge::Unmanaged => " [color=gray]",
+                Edge::Missing => " [style=dashed]",
+            };
+            Some(format!("  {src} -> {dst}{style};\n"))
+        })
+        .collect();
+    edges.sort();
+    output.extend(edges);
+    output.push_str("}\n");
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::NamedLink;
+
+    #[test]
+    fn graph() {
+        let alsa = Node {
+            name: "alsa_output.pci".to_string(),
+            description: Some("ALC257 Analog".to_string()),
+        };
+        let firefox = Node {
+            name: "Firefox".to_string(),
+            description: None,
+        };
+        let names: Vec<PortName> = ["ALC257 Analog:playback_FL", "ALC257 Analog:playback_FR", "Firefox:output_FL", "Firefox:output_FR"]
+            .iter()
+            .map(|name| PortName(name.to_string()))
+            .collect();
+        let port = |index: usize, node, direction| DotPort {
+            node: Some(node),
+            port_name: &names[index],
+            name: names[index].0.rsplit(':').next().unwrap(),
+            direction,
+        };
+        let ports = [
+            port(0, &alsa, PortDirection::In),
+            port(1, &alsa, PortDirection::In),
+            port(2, &firefox, PortDirection::Out),
+            port(3, &firefox, PortDirection::Out),
+        ];
+        let link = |src: usize, dst: usize, edge| {
+            let link = NamedLink {
+                src: names[src].clone(),
+                dst: names[dst].clone(),
+                on_exit: None,
+            };
+            (
+                GraphLink {
+                    link,
+                    src_node: Some(&firefox),
+                    dst_node: Some(&alsa),
+                },
+                edge,
+            )
+        };
+        let links = [link(2, 0, Edge::Managed), link(3, 1, Edge::Missing), link(3, 0, Edge::Unmanaged)];
+        assert_eq!(
+            dot(&ports, &links, &Filter::default()),
+            r#"digraph pipewire {
+  rankdir=LR;
+  node [shape=record];
+  subgraph cluster_0 {
+    label="Firefox";
+    n0_out [label="{<p0> output_FL|<p1> output_FR}"];
+  }
+  subgraph cluster_1 {
+    label="ALC257 Analog";
+    n1_in [label="{<p0> playback_FL|<p1> playback_FR}"];
+  }
+  n0_out:p0 -> n1_in:p0 [penwidth=2];
+  n0_out:p1 -> n1_in:p0 [color=gray];
+  n0_out:p1 -> n1_in:p1 [style=dashed];
+}
+"#
+        );
+        assert_eq!(escape("a{b}|<c> \"d\\"), "a\\{b\\}\\|\\<c\\> \\\"d\\\\");
+    }
+}
@@ -0,0 +1,271 @@
+use std::collections::BTreeMap;
+
+use regex::Regex;
+
+use crate::config::{self, NamedLink, NamedLinks};
+use crate::{Format, Node};
+
+/// Which links of the graph to dump; an empty filter matches everything
+#[derive(Debug, Clone, Default)]
+pub struct Filter {
+    /// Node names or descriptions, either end of the link must be on one of them
+    pub nodes: Vec<String>,
+    /// Port names, either end of the link must be one of them
+    pub ports: Vec<String>,
+    /// Either port name of the link must match
+    pub regex: Option<Regex>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct DumpOptions {
+    pub compress: bool,
+    pub group_by_node: bool,
+    pub filter: Filter,
+    pub format: Format,
+}
+
+/// A link of the graph along with the nodes of its ports
+#[derive(Debug)]
+pub struct GraphLink<'a> {
+    pub link: NamedLink,
+    pub src_node: Option<&'a Node>,
+    pub dst_node: Option<&'a Node>,
+}
+
+impl Filter {
+    fn node_matches(&self, node: Option<&Node>) -> bool {
+        node.is_some_and(|node| {
+            self.nodes
+                .iter()
+                .any(|name| *name == node.name || Some(name) == node.description.as_ref())
+        })
+    }
+
+    pub fn matches(&self, link: &GraphLink) -> bool {
+        let ports = [&link.link.src.0, &link.link.dst.0];
+        (self.nodes.is_empty() || self.node_matches(link.src_node) || self.node_matches(link.dst_node))
+            && (self.ports.is_empty() || ports.iter().any(|port| self.ports.contains(port)))
+            && self
+                .regex
+                .as_ref()
+                .is_none_or(|regex| ports.iter().any(|port| regex.is_match(port)))
+    }
+}
+
+fn sorted_links(links: Vec<NamedLink>, compress: bool) -> NamedLinks {
+    let mut links = NamedLinks(links);
+    links.0.sort();
+    if compress {
+        links = config::compress_links(&links);
+    }
+    links
+}
+
+/// Renders the links of the graph as a configuration, in YAML or JSON
+pub fn dump(links: Vec<GraphLink>, options: &DumpOptions) -> String {
+    let links: Vec<_> = links.into_iter().filter(|link| options.filter.matches(link)).collect();
+    // JSON has no comments to group with
+    if !options.group_by_node || options.format == Format::Json {
+        let links = sorted_links(links.into_iter().map(|link| link.link).collect(), options.compress);
+        let config = config::Config {
+            links,
+            ..config::Config::default()
+        };
+        return config.dump(match options.format {
+            Format::Json => config::ConfigFormat::Json,
+            _ => config::ConfigFormat::Yaml,
+        });
+    }
+
+    let mut groups: BTreeMap<Option<&str>, (Option<&Node>, Vec<NamedLink>)> = BTreeMap::new();
+    for link in links {
+        let group = groups
+            .entry(link.src_node.map(|node| node.name.as_str()))
+            .or_insert((link.src_node, vec![]));
+        group.1.push(link.link);
+    }
+    // YAML comments can't be serialized, so the groups are put together by hand
+    let mut output = String::from(if groups.is_empty() { "links: []\n" } else { "links:\n" });
+    for (node, links) in groups.into_values() {
+        let comment = match node {
+            Some(Node {
+                description: Some(description),
+                name,
+            }) => format!("{description} ({name})"),
+            Some(node) => node.name.clone(),
+            None => "Unknown node".to_string(),
+        };
+        output.push_str(&format!("# {comment}\n"));
+        let links = sorted_links(links, options.compress);
+        output.push_str(&serde_yaml::to_string(&links).expect("Failed to serialize yaml"));
+    }
+    output
+}
+
+/// A link as qpwgraph names it, by node and port name
+#[derive(Debug)]
+pub struct PatchbayItem {
+    /// pipewire-audio, pipewire-midi or pipewire-video
+    pub port_type: &'static str,
+    pub output: (String, String),
+    pub input: (String, String),
+}
+
+fn xml_escape(str: &str) -> String {
+    str.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Renders the links as a qpwgraph patchbay
+pub fn qpwgraph(items: &[PatchbayItem]) -> String {
+    let mut output = String::from("<!DOCTYPE patchbay>\n<patchbay name=\"pw-connections\">\n <items>\n");
+    for item in items {
+        output.push_str(&format!(
+            "  <item node-type=\"pipewire\" port-type=\"{}\">\n",
+            item.port_type
+        ));
+        for (tag, (node, port)) in [("output", &item.output), ("input", &item.input)] {
+            output.push_str(&format!(
+                "   <{tag} node=\"{}\" port=\"{}\"/>\n",
+                xml_escape(node),
+                xml_escape(port)
+            ));
+        }
+        output.push_str("  </item>\n");
+    }
+    output.push_str(" </items>\n</patchbay>\n");
+    output
+}
+
+fn shell_quote(str: &str) -> String {
+    format!("'{}'", str.replace('\'', "'\\''"))
+}
+
+/// Renders the links as a shell script of pw-link commands
+pub fn pw_link_script(links: &[NamedLink]) -> String {
+    let mut output = String::from("#!/bin/sh\n");
+    for link in links {
+        output.push_str(&format!("pw-link {} {}\n", shell_quote(&link.src.0), shell_quote(&link.dst.0)));
+    }
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn node(name: &str, description: &str) -> Node {
+        Node {
+            name: name.to_string(),
+            description: Some(description.to_string()),
+        }
+    }
+
+    fn graph_link<'a>(src: &str, dst: &str, src_node: &'a Node, dst_node: &'a Node) -> GraphLink<'a> {
+        GraphLink {
+            link: NamedLink::new(src, dst),
+            src_node: Some(src_node),
+            dst_node: Some(dst_node),
+        }
+    }
+
+    #[test]
+    fn filtered_and_grouped() {
+        let alsa = node("alsa_output.pci", "ALC257 Analog");
+        let firefox = node("Firefox", "Firefox");
+        let synth = node("synth", "Synthesizer");
+        let links = || {
+            vec![
+                graph_link("Firefox:output_FL", "ALC257 Analog:playback_FL", &firefox, &alsa),
+                graph_link("Firefox:output_FR", "ALC257 Analog:playback_FR", &firefox, &alsa),
+                graph_link("synth:out_2", "ALC257 Analog:playback_FR", &synth, &alsa),
+                graph_link("synth:out_1", "ALC257 Analog:playback_FL", &synth, &alsa),
+            ]
+        };
+
+        let options = DumpOptions {
+            filter: Filter {
+                nodes: vec!["Synthesizer".to_string()],
+                ..Filter::default()
+            },
+            ..DumpOptions::default()
+        };
+        assert_eq!(
+            dump(links(), &options),
+            "links:
+- src: synth:out_1
+  dst: ALC257 Analog:playback_FL
+- src: synth:out_2
+  dst: ALC257 Analog:playback_FR
+
+"
+        );
+
+        let options = DumpOptions {
+            filter: Filter {
+                regex: Some(Regex::new("_FR$").unwrap()),
+                ..Filter::default()
+            },
+            ..DumpOptions::default()
+        };
+        assert_eq!(dump(links(), &options).matches("- src:").count(), 2);
+
+        let options = DumpOptions {
+            group_by_node: true,
+            format: Format::Json,
+            ..options
+        };
+        let json: serde_json::Value = serde_json::from_str(&dump(links(), &options)).unwrap();
+        assert_eq!(json["links"][0]["src"], "Firefox:output_FR");
+        assert_eq!(json["links"].as_array().unwrap().len(), 2);
+
+        let options = DumpOptions {
+            compress: true,
+            group_by_node: true,
+            ..DumpOptions::default()
+        };
+        assert_eq!(
+            dump(links(), &options),
+            "links:
+# Firefox (Firefox)
+- src: Firefox:output_{FL,FR}
+  dst: ALC257 Analog:playback_{FL,FR}
+# Synthesizer (synth)
+- src: synth:out_{1..2}
+  dst: ALC257 Analog:playback_{FL,FR}
+"
+        );
+    }
+
+    #[test]
+    fn other_tools() {
+        let items = [PatchbayItem {
+            port_type: "pipewire-midi",
+            output: ("Midi Through".to_string(), "capture_0".to_string()),
+            input: ("R&B synth".to_string(), "midi_in".to_string()),
+        }];
+        assert_eq!(
+            qpwgraph(&items),
+            r#"<!DOCTYPE patchbay>
+<patchbay name="pw-connections">
+ <items>
+  <item node-type="pipewire" port-type="pipewire-midi">
+   <output node="Midi Through" port="capture_0"/>
+   <input node="R&amp;B synth" port="midi_in"/>
+  </item>
+ </items>
+</patchbay>
+"#
+        );
+        let import = crate::import::qpwgraph(&qpwgraph(&items), "x").unwrap();
+        assert_eq!(import.links.0[0].dst.0, "R&B synth:midi_in");
+
+        let link = NamedLink::new("Firefox:output_FL", "Bob's speakers:playback_FL");
+        assert_eq!(
+            pw_link_script(&[link]),
+            "#!/bin/sh\npw-link 'Firefox:output_FL' 'Bob'\\''s speakers:playback_FL'\n"
+        );
+    }
+}
@@ -4,6 +4,7 @@ use thiserror::Error;
 use crate::config;
 
 #[derive(Error, Debug)]
+#[allow(clippy::enum_variant_names)]
 pub(crate) enum Error {
     #[error(transparent)]
     IOError(#[from] io::Error),
@@ -13,4 +14,19 @@ pub(crate) enum Error {
 
     #[error(transparent)]
     PipewireError(#[from] pipewire::Error),
+
+    #[error("PipeWire error: {0}")]
+    ServerError(String),
+
+    #[error(transparent)]
+    ProtocolError(#[from] serde_yaml::Error),
+
+    #[error("{0}")]
+    ControlError(String),
+
+    #[error("{0}")]
+    LinkError(String),
+
+    #[error(transparent)]
+    DBusError(#[from] zbus::Error),
 }
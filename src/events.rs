@@ -0,0 +1,28 @@
+use std::sync::mpsc::{channel, Receiver, Sender};
+
+use crate::config::PortName;
+
+/// Changes in the PipeWire graph that others may want to follow
+#[derive(Debug, Clone)]
+pub enum Event {
+    PortAppeared { port: PortName },
+    PortRemoved { port: PortName },
+    LinkCreated { src: PortName, dst: PortName },
+    LinkLost { src: PortName, dst: PortName },
+}
+
+#[derive(Debug, Default)]
+pub struct Subscribers(Vec<Sender<Event>>);
+
+impl Subscribers {
+    pub fn subscribe(&mut self) -> Receiver<Event> {
+        let (tx, rx) = channel();
+        self.0.push(tx);
+        rx
+    }
+
+    // Subscribers that have gone away are dropped
+    pub fn send(&mut self, event: Event) {
+        self.0.retain(|tx| tx.send(event.clone()).is_ok());
+    }
+}
@@ -0,0 +1,111 @@
+use std::process::{Command, Stdio};
+use std::thread;
+
+use tracing::{info, warn};
+
+use crate::config::{Hook, HookEvent, NamedLink};
+use crate::Node;
+
+/// What happened
+#[derive(Debug, Clone, Copy)]
+pub enum Trigger<'a> {
+    Link(HookEvent, &'a NamedLink),
+    Device(&'a Node),
+}
+
+impl Trigger<'_> {
+    fn event(&self) -> HookEvent {
+        match self {
+            Trigger::Link(event, _) => *event,
+            Trigger::Device(_) => HookEvent::DeviceAppeared,
+        }
+    }
+
+    /// The variables the command is run with
+    pub fn environment(&self) -> Vec<(&'static str, String)> {
+        let mut environment = vec![("PWC_EVENT", self.event().name().to_string())];
+        match self {
+            Trigger::Link(_, link) => {
+                environment.push(("PWC_SRC", link.src.0.clone()));
+                environment.push(("PWC_DST", link.dst.0.clone()));
+            }
+            Trigger::Device(node) => environment.push(("PWC_NODE", node.name.clone())),
+        }
+        environment
+    }
+}
+
+pub fn matches(hook: &Hook, trigger: &Trigger) -> bool {
+    if hook.on != trigger.event() {
+        return false;
+    }
+    match trigger {
+        Trigger::Link(_, link) => {
+            hook.src.as_ref().is_none_or(|src| *src == link.src) && hook.dst.as_ref().is_none_or(|dst| *dst == link.dst)
+        }
+        Trigger::Device(node) => hook.node.as_ref().is_none_or(|name| {
+            node.name == *name || node.description.as_ref() == Some(name)
+        }),
+    }
+}
+
+/// Starts the commands of the matching hooks; they are waited for in the background
+pub fn run(hooks: &[Hook], trigger: &Trigger) {
+    for hook in hooks.iter().filter(|hook| matches(hook, trigger)) {
+        info!(event = trigger.event().name(), command = %hook.command, "Running hook");
+        let mut command = Command::new("sh");
+        command.arg("-c").arg(&hook.command).stdin(Stdio::null()).envs(trigger.environment());
+        match command.spawn() {
+            Ok(mut child) => {
+                let command = hook.command.clone();
+                thread::spawn(move || match child.wait() {
+                    Ok(status) if !status.success() => warn!(%command, %status, "Hook failed"),
+                    Ok(_) => (),
+                    Err(error) => warn!(%command, %error, "Failed to wait for hook"),
+                });
+            }
+            Err(error) => warn!(command = %hook.command, %error, "Failed to run hook"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{Config, ConfigFormat};
+
+    #[test]
+    fn triggers() {
+        let yaml = r#"
+links: []
+hooks:
+  - on: device_appeared
+    node: Synth
+    command: amidi -p hw:2 -S 'F0 7E 7F 06 01 F7'
+  - on: link_lost
+    dst: speaker:in
+    command: notify-send "$PWC_SRC lost"
+"#;
+        let config = Config::deserialize(yaml, "hooks.yaml", ConfigFormat::Yaml).unwrap();
+        let synth = Node {
+            name: "alsa_midi.synth".to_string(),
+            description: Some("Synth".to_string()),
+        };
+        let link = NamedLink::new("synth:out", "speaker:in");
+        let device = Trigger::Device(&synth);
+        let lost = Trigger::Link(HookEvent::LinkLost, &link);
+        assert!(matches(&config.hooks[0], &device));
+        assert!(!matches(&config.hooks[0], &lost));
+        assert!(matches(&config.hooks[1], &lost));
+        assert!(!matches(&config.hooks[1], &Trigger::Link(HookEvent::LinkCreated, &link)));
+        assert_eq!(
+            lost.environment(),
+            vec![
+                ("PWC_EVENT", "link_lost".to_string()),
+                ("PWC_SRC", "synth:out".to_string()),
+                ("PWC_DST", "speaker:in".to_string())
+            ]
+        );
+        assert_eq!(device.environment()[1], ("PWC_NODE", "alsa_midi.synth".to_string()));
+    }
+}
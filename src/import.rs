@@ -0,0 +1,369 @@
+use std::collections::{HashMap, HashSet};
+use std::io::{self, Read};
+
+use crate::config::{self, NamedLink, NamedLinks, ParseError, PortName};
+use crate::PortDirection;
+
+/// The tool a file comes from
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum ImportFormat {
+    /// qpwgraph patchbay
+    Qpwgraph,
+    /// Output of pw-link -l
+    PwLink,
+    /// Output of jack_lsp -c, or better -cp
+    JackLsp,
+    /// aj-snapshot file
+    AjSnapshot,
+}
+
+/// Links read from the file of another tool
+#[derive(Debug, Clone, Default)]
+pub struct Import {
+    pub links: NamedLinks,
+    /// The entries that aren't PipeWire links
+    pub skipped: Vec<String>,
+    /// The ports not found by resolve
+    pub unresolved: Vec<PortName>,
+}
+
+impl Import {
+    /// Sorts the links and folds them into braces
+    pub fn compressed(mut self) -> Import {
+        self.links.0.sort();
+        self.links.0.dedup();
+        self.links = config::compress_links(&self.links);
+        self
+    }
+
+    /// Renames the ports with the names given by lookup, keeping those it doesn't know
+    pub fn resolve(&mut self, lookup: impl Fn(&PortName, PortDirection) -> Option<PortName>) {
+        for link in self.links.0.iter_mut() {
+            for (name, direction) in [(&mut link.src, PortDirection::Out), (&mut link.dst, PortDirection::In)] {
+                match lookup(name, direction) {
+                    Some(resolved) => *name = resolved,
+                    None if !self.unresolved.contains(name) => self.unresolved.push(name.clone()),
+                    None => (),
+                }
+            }
+        }
+    }
+}
+
+/// Reads a file, or the standard input for -, guessing its format from the contents if not given
+pub fn read(filename: &str, format: Option<ImportFormat>) -> Result<Import, config::Error> {
+    let contents = if filename == "-" {
+        let mut contents = String::new();
+        io::stdin().read_to_string(&mut contents)?;
+        contents
+    } else {
+        std::fs::read_to_string(filename)?
+    };
+    let format = format.unwrap_or_else(|| guess_format(&contents));
+    match format {
+        ImportFormat::Qpwgraph => qpwgraph(&contents, filename),
+        ImportFormat::PwLink => pw_link(&contents, filename),
+        ImportFormat::JackLsp => jack_lsp(&contents, filename),
+        ImportFormat::AjSnapshot => aj_snapshot(&contents, filename),
+    }
+}
+
+fn guess_format(contents: &str) -> ImportFormat {
+    if contents.contains("<aj-snapshot") {
+        ImportFormat::AjSnapshot
+    } else if contents.trim_start().starts_with('<') {
+        ImportFormat::Qpwgraph
+    } else if contents.contains("|->") || contents.contains("|<-") {
+        ImportFormat::PwLink
+    } else {
+        ImportFormat::JackLsp
+    }
+}
+
+fn parse_error(filename: &str, message: String) -> config::Error {
+    config::Error::ParseError(ParseError {
+        filename: filename.to_string(),
+        message,
+    })
+}
+
+fn xml_document<'a>(contents: &'a str, filename: &str) -> Result<roxmltree::Document<'a>, config::Error> {
+    // qpwgraph writes a <!DOCTYPE patchbay>
+    let options = roxmltree::ParsingOptions {
+        allow_dtd: true,
+        ..roxmltree::ParsingOptions::default()
+    };
+    roxmltree::Document::parse_with_options(contents, options).map_err(|error| parse_error(filename, error.to_string()))
+}
+
+/// Reads a qpwgraph patchbay, where each item connects a port of an output node to a port of an
+/// input node; ports are named after their node as in PipeWire aliases
+pub fn qpwgraph(contents: &str, filename: &str) -> Result<Import, config::Error> {
+    let document = xml_document(contents, filename)?;
+    let root = document.root_element();
+    if !root.has_tag_name("patchbay") {
+        return Err(parse_error(filename, "not a qpwgraph patchbay".to_string()));
+    }
+    let mut import = Import::default();
+    let items = children(root, "items").flat_map(|items| children(items, "item"));
+    for item in items {
+        let end = |tag: &str| {
+            let end = children(item, tag).next()?;
+            Some(format!("{}:{}", end.attribute("node")?, end.attribute("port")?))
+        };
+        let (Some(src), Some(dst)) = (end("output"), end("input")) else {
+            let line = document.text_pos_at(item.range().start).row;
+            return Err(parse_error(filename, format!("item at line {line} lacks an output or an input port")));
+        };
+        if item.attribute("node-type").is_some_and(|node_type| node_type != "pipewire") {
+            import.skipped.push(format!("{src} -> {dst}"));
+            continue;
+        }
+        import.links.0.push(NamedLink::new(&src, &dst));
+    }
+    Ok(import)
+}
+
+/// Reads the output of pw-link -l: each port followed by the ports linked to it, indented
+pub fn pw_link(contents: &str, filename: &str) -> Result<Import, config::Error> {
+    let mut import = Import::default();
+    let mut port = None;
+    for (index, line) in contents.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        if !line.starts_with(char::is_whitespace) {
+            port = Some(line.trim_end());
+            continue;
+        }
+        let line = line.trim();
+        match (port, line.strip_prefix("|->"), line.strip_prefix("|<-")) {
+            (Some(port), Some(dst), _) => import.links.0.push(NamedLink::new(port, dst.trim())),
+            (Some(port), _, Some(src)) => import.links.0.push(NamedLink::new(src.trim(), port)),
+            _ => return Err(parse_error(filename, format!("unexpected line {}: {line}", index + 1))),
+        }
+    }
+    Ok(import)
+}
+
+// Guesses the direction of a JACK port from the usual port names, like system:capture_1
+fn named_direction(port: &str) -> Option<PortDirection> {
+    let name = port.split_once(':').map_or(port, |(_, name)| name).to_lowercase();
+    let words: Vec<&str> = name.split(|c: char| !c.is_alphabetic()).collect();
+    if words.iter().any(|word| matches!(*word, "capture" | "output" | "out" | "monitor")) {
+        Some(PortDirection::Out)
+    } else if words.iter().any(|word| matches!(*word, "playback" | "input" | "in")) {
+        Some(PortDirection::In)
+    } else {
+        None
+    }
+}
+
+/// Reads the output of jack_lsp -c: each port followed by its connections, indented with
+/// spaces, and with -p its properties, indented with a tab. Without the properties, the
+/// directions are guessed from the port names, or else taken from the first listing.
+pub fn jack_lsp(contents: &str, filename: &str) -> Result<Import, config::Error> {
+    let mut outputs: HashMap<&str, bool> = HashMap::new();
+    let mut connections = vec![];
+    let mut port = None;
+    for (index, line) in contents.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let Some(indented) = line.strip_prefix(char::is_whitespace) else {
+            port = Some(line.trim_end());
+            continue;
+        };
+        let Some(port) = port else {
+            return Err(parse_error(filename, format!("unexpected line {}: {}", index + 1, line.trim())));
+        };
+        if let Some(properties) = indented.trim_start().strip_prefix("properties:") {
+            outputs.insert(port, properties.split(',').any(|property| property.trim() == "output"));
+        } else if line.starts_with(' ') {
+            connections.push((port, indented.trim()));
+        }
+        // other lines indented with a tab are types or latencies
+    }
+    let output = |port: &str| {
+        outputs.get(port).copied().or_else(|| named_direction(port).map(|direction| direction == PortDirection::Out))
+    };
+    let mut import = Import::default();
+    let mut seen = HashSet::new();
+    for (port, peer) in connections {
+        // each connection is listed from both ports
+        if !seen.insert(if port < peer { (port, peer) } else { (peer, port) }) {
+            continue;
+        }
+        match (output(port), output(peer)) {
+            (Some(false), _) | (_, Some(true)) => import.links.0.push(NamedLink::new(peer, port)),
+            _ => import.links.0.push(NamedLink::new(port, peer)),
+        }
+    }
+    Ok(import)
+}
+
+fn children<'a, 'input: 'a>(
+    node: roxmltree::Node<'a, 'input>,
+    tag: &'a str,
+) -> impl Iterator<Item = roxmltree::Node<'a, 'input>> {
+    node.children().filter(move |child| child.has_tag_name(tag))
+}
+
+/// Reads an aj-snapshot file; its ALSA connections are sequencer ones, not PipeWire links
+pub fn aj_snapshot(contents: &str, filename: &str) -> Result<Import, config::Error> {
+    let document = xml_document(contents, filename)?;
+    let root = document.root_element();
+    if !root.has_tag_name("aj-snapshot") {
+        return Err(parse_error(filename, "not an aj-snapshot file".to_string()));
+    }
+    let attribute = |node: roxmltree::Node, name| node.attribute(name).unwrap_or_default().to_string();
+    let mut import = Import::default();
+    for system in root.children().filter(|node| node.is_element()) {
+        let jack = system.has_tag_name("jack");
+        for client in children(system, "client") {
+            for port in children(client, "port") {
+                // JACK ports have names, ALSA ones numbers
+                let src = format!("{}:{}", attribute(client, "name"), attribute(port, if jack { "name" } else { "id" }));
+                for connection in children(port, "connection") {
+                    if jack {
+                        import.links.0.push(NamedLink::new(&src, &attribute(connection, "port")));
+                    } else {
+                        let dst = format!("{}:{}", attribute(connection, "client"), attribute(connection, "port"));
+                        import.skipped.push(format!("{src} -> {dst}"));
+                    }
+                }
+            }
+        }
+    }
+    Ok(import)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn patchbay() {
+        let xml = r#"<!DOCTYPE patchbay>
+<patchbay version="0.4.2" name="studio">
+ <items>
+  <item port-type="pipewire-audio" node-type="pipewire">
+   <output port="capture_FR" node="ALC257 Analog"/>
+   <input port="input_FR" node="Firefox"/>
+  </item>
+  <item port-type="pipewire-audio" node-type="pipewire">
+   <output port="capture_FL" node="ALC257 Analog"/>
+   <input port="input_FL" node="Firefox"/>
+  </item>
+  <item port-type="alsa-midi" node-type="alsa">
+   <output port="Midi Through Port-0" node="Midi Through"/>
+   <input port="VirMIDI 4-0" node="Virtual Raw MIDI 4-0"/>
+  </item>
+ </items>
+</patchbay>
+"#;
+        let import = qpwgraph(xml, "studio.qpwgraph").unwrap().compressed();
+        assert_eq!(
+            config::Config {
+                links: import.links,
+                ..config::Config::default()
+            }
+            .dump(config::ConfigFormat::Yaml),
+            "links:
+- src: ALC257 Analog:capture_{FL,FR}
+  dst: Firefox:input_{FL,FR}
+
+"
+        );
+        assert_eq!(import.skipped, vec!["Midi Through:Midi Through Port-0 -> Virtual Raw MIDI 4-0:VirMIDI 4-0"]);
+
+        let error = qpwgraph("<patchbay><items>\n<item><output node=\"a\" port=\"b\"/></item></items></patchbay>", "x")
+            .unwrap_err();
+        assert_eq!(error.to_string(), "Failed to parse x: item at line 2 lacks an output or an input port");
+        assert!(qpwgraph("<links/>", "x").is_err());
+    }
+
+    #[test]
+    fn other_tools() {
+        let pw_link_output = "alsa_input.pci:capture_FL
+  |-> Firefox:input_FL
+Firefox:input_FL
+  |<- alsa_input.pci:capture_FL
+  |<- synth:out_1
+";
+        let jack_lsp_output = "system:capture_1
+   PulseAudio JACK Sink:front-left
+	properties: output,physical,terminal,
+PulseAudio JACK Sink:front-left
+   system:capture_1
+	properties: input,
+";
+        let aj_snapshot_file = r#"<?xml version="1.0" encoding="utf-8"?>
+<aj-snapshot>
+<alsa>
+  <client name="Midi Through">
+    <port id="0">
+      <connection client="VirMIDI 4-0" port="0" />
+    </port>
+  </client>
+</alsa>
+<jack>
+  <client name="system">
+    <port name="capture_1">
+      <connection port="PulseAudio JACK Sink:front-left" />
+    </port>
+  </client>
+</jack>
+</aj-snapshot>
+"#;
+        assert_eq!(guess_format(pw_link_output), ImportFormat::PwLink);
+        assert_eq!(guess_format(jack_lsp_output), ImportFormat::JackLsp);
+        assert_eq!(guess_format(aj_snapshot_file), ImportFormat::AjSnapshot);
+
+        let import = pw_link(pw_link_output, "-").unwrap().compressed();
+        assert_eq!(
+            import.links.0,
+            vec![NamedLink::new("alsa_input.pci:capture_FL", "Firefox:input_FL"), NamedLink::new("synth:out_1", "Firefox:input_FL")]
+        );
+        assert!(pw_link("  |-> Firefox:input_FL", "-").is_err());
+
+        let sink = vec![NamedLink::new("system:capture_1", "PulseAudio JACK Sink:front-left")];
+        assert_eq!(jack_lsp(jack_lsp_output, "-").unwrap().links.0, sink);
+        // jack_lsp -c, without the properties
+        let jack_lsp_output = "system:capture_1
+   PulseAudio JACK Source:front-left
+system:capture_2
+system:playback_1
+   PulseAudio JACK Sink:front-left
+system:playback_2
+PulseAudio JACK Sink:front-left
+   system:playback_1
+PulseAudio JACK Sink:front-right
+PulseAudio JACK Source:front-left
+   system:capture_1
+PulseAudio JACK Source:front-right
+";
+        assert_eq!(guess_format(jack_lsp_output), ImportFormat::JackLsp);
+        assert_eq!(
+            jack_lsp(jack_lsp_output, "-").unwrap().links.0,
+            vec![
+                NamedLink::new("system:capture_1", "PulseAudio JACK Source:front-left"),
+                NamedLink::new("PulseAudio JACK Sink:front-left", "system:playback_1")
+            ]
+        );
+        assert!(jack_lsp("   system:capture_1\n", "-").is_err());
+
+        let import = aj_snapshot(aj_snapshot_file, "studio.snap").unwrap();
+        assert_eq!(import.links.0, sink);
+        assert_eq!(import.skipped, vec!["Midi Through:0 -> VirMIDI 4-0:0"]);
+
+        let mut import = pw_link(pw_link_output, "-").unwrap();
+        import.resolve(|name, direction| match (name.0.as_str(), direction) {
+            ("alsa_input.pci:capture_FL", PortDirection::Out) => Some(PortName("ALC257 Analog:capture_FL".to_string())),
+            ("Firefox:input_FL", PortDirection::In) => Some(name.clone()),
+            _ => None,
+        });
+        assert_eq!(import.links.0[0], NamedLink::new("ALC257 Analog:capture_FL", "Firefox:input_FL"));
+        assert_eq!(import.unresolved, vec![PortName("synth:out_1".to_string())]);
+    }
+}
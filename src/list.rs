@@ -0,0 +1,129 @@
+use regex::Regex;
+use serde_derive::Serialize;
+
+use crate::{Format, PortDirection};
+
+/// One row of list-ports
+#[derive(Debug, Serialize)]
+pub struct PortEntry {
+    /// The name used in the configuration: the alias if there is one, otherwise the name
+    pub port: String,
+    pub name: String,
+    pub alias: Option<String>,
+    pub direction: &'static str,
+    pub node: Option<String>,
+    pub node_description: Option<String>,
+    pub media_type: Option<String>,
+    /// The ports this one is linked to
+    pub links: Vec<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct ListOptions {
+    pub nodes: Vec<String>,
+    pub regex: Option<Regex>,
+    pub direction: Option<PortDirection>,
+    pub format: Format,
+}
+
+impl ListOptions {
+    pub fn matches(&self, entry: &PortEntry) -> bool {
+        let on_node = |name: &String| Some(name) == entry.node.as_ref() || Some(name) == entry.node_description.as_ref();
+        let direction = match self.direction {
+            Some(PortDirection::In) => Some("in"),
+            Some(PortDirection::Out) => Some("out"),
+            None => None,
+        };
+        (self.nodes.is_empty() || self.nodes.iter().any(on_node))
+            && self
+                .regex
+                .as_ref()
+                .is_none_or(|regex| regex.is_match(&entry.port) || regex.is_match(&entry.name))
+            && direction.is_none_or(|direction| direction == entry.direction)
+    }
+}
+
+/// Formats the entries as a table with a header
+pub fn table(entries: &[PortEntry]) -> String {
+    let rows: Vec<[String; 6]> = entries
+        .iter()
+        .map(|entry| {
+            [
+                entry.direction.to_string(),
+                entry.node_description.clone().or(entry.node.clone()).unwrap_or_default(),
+                entry.port.clone(),
+                entry.name.clone(),
+                entry.media_type.clone().unwrap_or_default(),
+                entry.links.join(", "),
+            ]
+        })
+        .collect();
+    let header = ["DIR", "NODE", "PORT", "NAME", "MEDIA", "LINKS"].map(String::from);
+    let mut widths = [0; 6];
+    for row in std::iter::once(&header).chain(rows.iter()) {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+    let mut output = String::new();
+    for row in std::iter::once(&header).chain(rows.iter()) {
+        let line: Vec<String> = row
+            .iter()
+            .zip(widths)
+            .map(|(cell, width)| format!("{cell:<width$}"))
+            .collect();
+        output.push_str(line.join("  ").trim_end());
+        output.push('\n');
+    }
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(port: &str, direction: &'static str, links: &[&str]) -> PortEntry {
+        PortEntry {
+            port: port.to_string(),
+            name: port.rsplit(':').next().unwrap().to_string(),
+            alias: Some(port.to_string()),
+            direction,
+            node: Some("alsa_output.pci".to_string()),
+            node_description: Some("ALC257 Analog".to_string()),
+            media_type: Some("32 bit float mono audio".to_string()),
+            links: links.iter().map(|link| link.to_string()).collect(),
+        }
+    }
+
+    #[test]
+    fn listing() {
+        let entries = [
+            entry("ALC257 Analog:capture_FL", "out", &[]),
+            entry("ALC257 Analog:playback_FL", "in", &["Firefox:output_FL", "synth:out_1"]),
+        ];
+        assert_eq!(
+            table(&entries),
+            "DIR  NODE           PORT                       NAME         MEDIA                    LINKS
+out  ALC257 Analog  ALC257 Analog:capture_FL   capture_FL   32 bit float mono audio
+in   ALC257 Analog  ALC257 Analog:playback_FL  playback_FL  32 bit float mono audio  Firefox:output_FL, synth:out_1
+"
+        );
+
+        let options = ListOptions {
+            nodes: vec!["ALC257 Analog".to_string()],
+            regex: Some(Regex::new("^playback").unwrap()),
+            direction: None,
+            format: Format::Yaml,
+        };
+        assert!(!options.matches(&entries[0]));
+        assert!(options.matches(&entries[1]));
+        let options = ListOptions {
+            nodes: vec![],
+            regex: None,
+            direction: Some(PortDirection::Out),
+            format: Format::Yaml,
+        };
+        assert!(options.matches(&entries[0]));
+        assert!(!options.matches(&entries[1]));
+    }
+}
@@ -0,0 +1,55 @@
+use std::io::{self, IsTerminal};
+
+use tracing::level_filters::LevelFilter;
+use tracing_subscriber::prelude::*;
+use tracing_subscriber::{fmt, EnvFilter};
+
+/// The level of our messages: info, one step more for each --verbose and one less for each --quiet
+pub fn level(verbose: u8, quiet: u8) -> LevelFilter {
+    const LEVELS: [LevelFilter; 6] = [
+        LevelFilter::OFF,
+        LevelFilter::ERROR,
+        LevelFilter::WARN,
+        LevelFilter::INFO,
+        LevelFilter::DEBUG,
+        LevelFilter::TRACE,
+    ];
+    let index = (3 + i32::from(verbose) - i32::from(quiet)).clamp(0, 5);
+    LEVELS[index as usize]
+}
+
+/// Sends the messages to the standard error, or to the journal. RUST_LOG overrides the levels;
+/// otherwise the libraries only tell about warnings and errors.
+pub fn init(verbose: u8, quiet: u8, journald: bool) -> io::Result<()> {
+    let filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| {
+        let level = level(verbose, quiet);
+        EnvFilter::new(format!("{},pw_connections={level}", level.min(LevelFilter::WARN)))
+    });
+    let registry = tracing_subscriber::registry().with(filter);
+    if journald {
+        let journald = tracing_journald::layer()?.with_syslog_identifier("pw-connections".to_string());
+        registry.with(journald).init();
+    } else {
+        let stderr = fmt::layer()
+            .with_writer(io::stderr)
+            .with_target(false)
+            .with_ansi(io::stderr().is_terminal());
+        registry.with(stderr).init();
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn levels() {
+        assert_eq!(level(0, 0), LevelFilter::INFO);
+        assert_eq!(level(1, 0), LevelFilter::DEBUG);
+        assert_eq!(level(5, 0), LevelFilter::TRACE);
+        assert_eq!(level(0, 1), LevelFilter::WARN);
+        assert_eq!(level(0, 4), LevelFilter::OFF);
+        assert_eq!(level(2, 1), LevelFilter::DEBUG);
+    }
+}
@@ -1,10 +1,31 @@
+mod audit;
 mod config;
+mod control;
+mod dbus;
+mod diff;
+mod dot;
+mod dump;
 mod error;
+mod events;
+mod hooks;
+mod import;
+mod list;
+mod logging;
+mod midi;
+mod monitor;
+mod osc;
+mod plan;
+mod save;
+mod signals;
+mod snapshot;
+mod status;
+mod tui;
 
 use std::cell::RefCell;
 use std::collections::{HashMap, HashSet};
 use std::convert::From;
 use std::marker::PhantomData;
+use std::path::PathBuf;
 use std::rc::Rc;
 use std::sync::mpsc::{channel, Receiver, RecvTimeoutError, Sender};
 use std::thread;
@@ -15,7 +36,8 @@ use libspa as spa;
 use pipewire as pw;
 use spa::ReadableDict;
 
-use clap::{Parser, ArgGroup};
+use clap::{ArgAction, ArgGroup, CommandFactory, Parser, Subcommand, ValueEnum};
+use tracing::{debug, error, info, warn};
 
 use config::PortName;
 
@@ -29,17 +51,19 @@ use config::PortName;
 
 fn error_callback(a: u32, b: i32, c: i32, msg: &str,
 		  tx: &pw::channel::Sender<PWRequest>) {
-    eprintln!("error: {a:?} {b:?} {c:?} {msg}");
+    error!(id = a, seq = b, res = c, "PipeWire error: {msg}");
 
     // could be exiting already
-    let _ignore = tx.send(PWRequest::Quit(QuitReason::Error));
+    let _ignore = tx.send(PWRequest::Quit(QuitReason::Error(msg.to_string())));
 }
 
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
+#[command(subcommand_negates_reqs = true)]
 #[clap(group(
     ArgGroup::new("mode")
         .required(true)
+        .multiple(true)
         .args(&["config", "dump"]),
 ))]
 struct Args {
@@ -47,10 +71,239 @@ struct Args {
     #[arg(short, long)]
     config: Option<String>,
 
+    /// Format of the config file read and saved; guessed from its extension by default. --format is for what is printed
+    #[arg(long, value_enum, requires = "config")]
+    config_format: Option<config::ConfigFormat>,
+
     #[arg(short, long)]
     dump: bool,
+
+    /// With --dump, fold links that differ only by a number or a word into braces
+    #[arg(long, requires = "dump")]
+    compress: bool,
+
+    /// With --dump, only links from or to this node, by name or description; can be repeated
+    #[arg(long, value_name = "NAME", requires = "dump")]
+    node: Vec<String>,
+
+    /// With --dump, only links from or to this port; can be repeated
+    #[arg(long, value_name = "NAME", requires = "dump")]
+    port: Vec<String>,
+
+    /// With --dump, only links from or to a port matching this regular expression
+    #[arg(long, value_name = "REGEX", requires = "dump", value_parser = regex::Regex::new)]
+    regex: Option<regex::Regex>,
+
+    /// With --dump, group the links by source node, with the node description as a comment
+    #[arg(long, requires = "dump")]
+    group_by_node: bool,
+
+    /// Make the configured links that can be made, leave them in place and exit
+    #[arg(long, requires = "config", conflicts_with = "dump")]
+    once: bool,
+
+    /// With --once, wait up to this many seconds for missing ports
+    #[arg(long, value_name = "SECONDS", requires = "once")]
+    timeout: Option<u64>,
+
+    /// Print the changes the configuration would make to the graph, without making them
+    #[arg(long, requires = "config", conflicts_with_all = ["once", "dump"])]
+    dry_run: bool,
+
+    /// Format of what is printed; --config-format is for the config file
+    #[arg(long, value_enum, global = true)]
+    format: Option<Format>,
+
+    /// Path of the control socket; defaults to pw-connections.sock in $XDG_RUNTIME_DIR
+    #[arg(long, global = true)]
+    control_socket: Option<PathBuf>,
+
+    /// Claim a name on the D-Bus session bus and serve the control interface there
+    #[arg(long, value_name = "NAME", num_args = 0..=1, default_missing_value = dbus::DEFAULT_NAME)]
+    dbus: Option<String>,
+
+    /// Address of the D-Bus bus to use instead of the session bus
+    #[arg(long, requires = "dbus")]
+    dbus_address: Option<String>,
+
+    /// Listen to OSC control messages on this UDP port of localhost
+    #[arg(long, value_name = "PORT")]
+    osc_port: Option<u16>,
+
+    /// Show more messages; can be repeated
+    #[arg(short, long, action = ArgAction::Count, global = true)]
+    verbose: u8,
+
+    /// Show fewer messages; can be repeated
+    #[arg(short, long, action = ArgAction::Count, global = true, conflicts_with = "verbose")]
+    quiet: u8,
+
+    /// Append a JSON line to this file for each link made or removed, with the reason
+    #[arg(long, value_name = "FILE")]
+    audit_log: Option<PathBuf>,
+
+    /// Send the messages to the systemd journal instead of the standard error
+    #[arg(long, global = true)]
+    journald: bool,
+
+    /// Show the ports in a terminal UI to link them by hand and save the links into the config
+    #[arg(long, requires = "config", conflicts_with_all = ["once", "dump", "dry_run"])]
+    tui: bool,
+
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Show the state of each configured link of the running daemon
+    Status,
+
+    /// Merge the links of the running daemon into its config file
+    Save,
+
+    /// Link ports by name, with brace expansion; the links are removed on exit unless lingering
+    Link {
+        src: String,
+        dst: String,
+
+        /// Leave the links in place and exit once they are made
+        #[arg(long)]
+        linger: bool,
+    },
+
+    /// Remove the links between ports by name, with brace expansion
+    Unlink {
+        src: String,
+        dst: String,
+    },
+
+    /// Compare the links of the configuration with the links of the graph
+    Diff,
+
+    /// Print the ports and links as they appear and disappear, until interrupted
+    Monitor,
+
+    /// Print the links saved by another tool as a configuration
+    Import {
+        /// File to read, - for the standard input
+        file: String,
+
+        /// Tool the file comes from; guessed from its contents by default
+        #[arg(long, value_enum)]
+        from: Option<import::ImportFormat>,
+
+        /// Rename the ports to the names of the running graph
+        #[arg(long)]
+        resolve: bool,
+    },
+
+    /// Record the links made and removed until interrupted into the top level links of a config file
+    Learn {
+        /// Config file to update, created if missing
+        #[arg(short, long)]
+        config: String,
+    },
+
+    /// Save all the links of the graph to a file, or put them back
+    Snapshot {
+        #[command(subcommand)]
+        command: SnapshotCommand,
+    },
+
+    /// List the ports of the graph with their names and links
+    ListPorts {
+        /// Only ports of this node, by name or description; can be repeated
+        #[arg(long, value_name = "NAME")]
+        node: Vec<String>,
+
+        /// Only ports whose alias or name matches this regular expression
+        #[arg(long, value_parser = regex::Regex::new)]
+        regex: Option<regex::Regex>,
+
+        #[arg(long, value_enum)]
+        direction: Option<PortDirection>,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum SnapshotCommand {
+    /// Write the links of the graph with everything known of their ports
+    Save {
+        file: String,
+    },
+
+    /// Make the links of a snapshot and leave them in place
+    Restore {
+        file: String,
+
+        /// Also remove the links that aren't in the snapshot
+        #[arg(long)]
+        prune: bool,
+    },
+}
+
+/// What the process is run for
+#[derive(Debug, Clone)]
+enum Mode {
+    Daemon,
+    Dump(dump::DumpOptions),
+    Link { linger: bool },
+    Unlink(config::NamedLinks),
+    Once { timeout: Option<time::Duration> },
+    DryRun(Format),
+    Diff,
+    Monitor(Format),
+    ListPorts(list::ListOptions),
+    Import(import::Import, config::ConfigFormat),
+    Learn,
+    SnapshotSave(String),
+    Restore(snapshot::Restore),
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, ValueEnum)]
+enum Format {
+    /// YAML for a config, plain text for the rest
+    #[default]
+    #[value(alias = "text")]
+    Yaml,
+    Json,
+    /// Graphviz, for --dump
+    Dot,
+    /// qpwgraph patchbay, for --dump
+    Qpwgraph,
+    /// Shell script of pw-link commands, for --dump
+    PwLinkScript,
 }
 
+impl Mode {
+    fn is_daemon(&self) -> bool {
+        matches!(self, Mode::Daemon)
+    }
+
+    // Whether the graph is only looked at
+    fn read_only(&self) -> bool {
+        matches!(self, Mode::Dump(_) | Mode::DryRun(_) | Mode::Diff | Mode::Monitor(_) | Mode::ListPorts(_) | Mode::Import(..) | Mode::Learn
+                 | Mode::SnapshotSave(_))
+    }
+
+    fn linger(&self) -> bool {
+        matches!(self, Mode::Link { linger: true } | Mode::Once { .. } | Mode::Restore(_))
+    }
+
+    // How long a one-shot run may take to get its changes made; None if it runs until stopped
+    fn deadline(&self) -> Option<time::Duration> {
+        match self {
+            Mode::Link { linger: true } | Mode::Unlink(_) | Mode::Restore(_) => Some(ONE_SHOT_TIMEOUT),
+            Mode::Once { timeout } => Some(timeout.unwrap_or_default() + ONE_SHOT_TIMEOUT),
+            _ => None,
+        }
+    }
+}
+
+// How long PipeWire gets to carry out the changes of a one-shot run
+const ONE_SHOT_TIMEOUT: time::Duration = time::Duration::from_secs(10);
+
 #[derive(Debug, Clone, Eq, Hash, PartialOrd, PartialEq)]
 struct ObjectId(String);
 
@@ -131,18 +384,24 @@ struct Object {
 enum Message {
     Object(Object),
     Remove(ObjectId),
+    LinkFailed(config::NamedLink, String),
+    Destroyed(LinkObjectId), // PipeWire has handled the request to destroy the link
+    Midi(midi::MidiMessage),
+    Shutdown,
 }
 
 #[derive(Debug, Clone)]
 enum QuitReason {
-    Error,
+    Error(String), // reported by PipeWire
     Done,
+    Failed(String),
 }
 
 /// Request to PipeWire
 #[derive(Debug)]
 enum PWRequest {
-    MakeLink((Port, Port)),
+    MakeLink(Box<(Port, Port)>, bool), // whether the link outlives us
+    DestroyLink(LinkObjectId),
     Quit(QuitReason),
 }
 
@@ -170,7 +429,7 @@ fn global_remove_callback(tx: &Sender<Message>, id: u32) {
     //tx.send(Message {}).expect("wtf");
 }
 
-#[derive(Debug, Clone, Eq, Hash, PartialOrd, PartialEq)]
+#[derive(Debug, Clone, Eq, Hash, PartialOrd, PartialEq, ValueEnum)]
 enum PortDirection {
     In,
     Out,
@@ -182,12 +441,22 @@ struct NodeId(String);
 #[derive(Debug, Clone, Eq, Hash, PartialOrd, PartialEq)]
 struct PortId(String);
 
+#[derive(Debug, Clone)]
+struct Node {
+    name: String,
+    description: Option<String>,
+}
+
 #[derive(Debug, Clone)]
 struct Port {
     node_id: NodeId,
-    port_name: PortName,
+    port_name: PortName, // alias if there is one, otherwise name
     port_id: PortId,
     port_direction: PortDirection,
+    name: String,
+    alias: Option<String>,
+    media_type: Option<String>,
+    object_path: Option<String>,
 }
 
 impl From<&String> for PortDirection {
@@ -213,38 +482,461 @@ type Links = HashMap<(PortObjectId<Output>, PortObjectId<Input>), Vec<Link>>;
 
 #[derive(Debug)]
 struct Main {
+    nodes: HashMap<NodeId, Node>,
     ports: Ports,
     links: Links,
     links_by_id: HashMap<LinkObjectId, (PortObjectId<Output>, PortObjectId<Input>)>,
     config_links: config::NamedLinks, // desired state
     failed_pairs: HashSet<(PortName, PortName)>, // remember failed pairs to reduce logging
-
-    dump_and_exit: bool,
+    link_states: HashMap<config::NamedLink, status::LinkStatus>,
+    pending_unlinks: Vec<config::NamedLink>, // links no longer desired, to be destroyed
+    pending_destroys: HashMap<LinkObjectId, config::NamedLink>, // asked to be destroyed, PipeWire hasn't answered yet
+    failed_unlinks: Vec<config::NamedLink>, // still in the graph after PipeWire answered
+    initial_links: Option<Vec<config::NamedLink>>, // links that existed when we started, for restoring
+
+    config: config::Config,
+    config_filename: Option<String>,
+    config_format: Option<config::ConfigFormat>,
+    profile: Option<String>,
+    extra_links: config::NamedLinks, // added at runtime, until reload
+    removed_links: config::NamedLinks, // removed at runtime, until reload
+
+    subscribers: events::Subscribers,
+    audit_log: Option<audit::AuditLog>,
+    audit_reasons: HashMap<config::NamedLink, audit::Reason>, // why the next change of the link happens
+    audit_pending: HashMap<config::NamedLink, (bool, audit::Reason)>, // asked for, with whether it lingers, not in the graph yet
+    audit_owned: HashMap<config::NamedLink, LinkObjectId>, // made by us, gone when we exit
+
+    mode: Mode,
+    graph_synced: bool, // the graph of this connection has settled, what appears now is new
+    seen_ports: HashSet<PortName>, // since the start, to tell new ports from those coming back
+    hook_runner: fn(&[config::Hook], &hooks::Trigger), // hooks::run, unless testing
+    started: time::Instant,
+    shutdown_requested: bool, // by the terminal UI
 }
 
 impl Main {
-    fn new(config_links: config::NamedLinks, dump_and_exit: bool) -> Self {
+    fn new(config: config::Config, config_filename: Option<String>, config_format: Option<config::ConfigFormat>, mode: Mode) -> Self {
+        let profile = config.profile.clone();
+        let config_links = config.profile_links(profile.as_deref())
+            .expect("Profile is checked by Config::load");
+        let link_states = config_links.0.iter()
+            .map(|link| (link.clone(), status::LinkStatus::default()))
+            .collect();
+        let pending_unlinks = match &mode {
+            Mode::Unlink(links) => links.0.clone(),
+            _ => Vec::new(),
+        };
         Main {
+            nodes: HashMap::default(),
             ports: HashMap::default(),
             links: HashMap::default(),
             links_by_id: HashMap::default(),
             config_links,
 	    failed_pairs: HashSet::default(),
-	    dump_and_exit,
+	    link_states,
+	    pending_unlinks,
+	    pending_destroys: HashMap::default(),
+	    failed_unlinks: Vec::new(),
+	    initial_links: None,
+	    config,
+	    config_filename,
+	    config_format,
+	    profile,
+	    extra_links: config::NamedLinks::default(),
+	    removed_links: config::NamedLinks::default(),
+	    subscribers: events::Subscribers::default(),
+	    audit_log: None,
+	    audit_reasons: HashMap::default(),
+	    audit_pending: HashMap::default(),
+	    audit_owned: HashMap::default(),
+	    mode,
+	    graph_synced: false,
+	    seen_ports: HashSet::default(),
+	    hook_runner: hooks::run,
+	    started: time::Instant::now(),
+	    shutdown_requested: false,
+        }
+    }
+
+    // Forget the graph of a previous PipeWire connection, but keep the link states
+    fn reset_graph(&mut self) {
+        self.nodes.clear();
+        self.ports.clear();
+        self.links.clear();
+        self.links_by_id.clear();
+        self.pending_destroys.clear();
+        self.graph_synced = false;
+    }
+
+    fn status(&self) -> Vec<status::StatusEntry> {
+        self.config_links.0.iter()
+            .filter_map(|link| self.link_states.get(link)
+                        .map(|link_status| status::StatusEntry::new(link, link_status)))
+            .collect()
+    }
+
+    fn handle_request(&mut self, request: control::Request) -> control::Response {
+        let result = match request {
+            control::Request::Status => return control::Response::Status(self.status()),
+            control::Request::AddLink(link) => self.add_link(link),
+            control::Request::RemoveLink(link) => self.remove_link(link),
+            control::Request::AddExactLink(link) => self.add_links(config::NamedLinks(vec![link])),
+            control::Request::RemoveExactLink(link) => self.remove_links(config::NamedLinks(vec![link])),
+            control::Request::SetProfile(profile) => self.set_profile(profile),
+            control::Request::Reload => self.reload(),
+            control::Request::Save => self.save(),
+        };
+        match result {
+            Ok(()) => control::Response::Ok,
+            Err(error) => control::Response::Error(error.to_string()),
+        }
+    }
+
+    // Recomputes the desired links after the config, profile or runtime changes
+    fn update_config_links(&mut self) -> Result<(), config::Error> {
+        let mut links = self.config.profile_links(self.profile.as_deref())?;
+        links.0.extend(self.extra_links.0.iter().cloned());
+        links.0.retain(|link| !self.removed_links.0.contains(link));
+        self.pending_unlinks.retain(|link| !links.0.contains(link));
+        for link in self.config_links.0.clone() {
+            if !links.0.contains(&link) {
+                info!(src = %link.src.0, dst = %link.dst.0, "Link no longer configured");
+                self.audit_reason(&link, audit::Reason::Reload);
+                self.pending_unlinks.push(link);
+            }
+        }
+        for link in links.0.iter() {
+            if !self.config_links.0.contains(link) {
+                self.audit_reason(link, audit::Reason::Reload);
+            }
+        }
+        self.link_states.retain(|link, _| links.0.contains(link));
+        for link in links.0.iter() {
+            self.link_states.entry(link.clone()).or_default();
+        }
+        self.config_links = links;
+        Ok(())
+    }
+
+    fn add_link(&mut self, link: config::NamedLink) -> Result<(), config::Error> {
+        self.add_links(config::expand_links(config::NamedLinks(vec![link]))?)
+    }
+
+    // Without brace expansion
+    fn add_links(&mut self, links: config::NamedLinks) -> Result<(), config::Error> {
+        for link in links.0 {
+            self.removed_links.0.retain(|removed| *removed != link);
+            if !self.config_links.0.contains(&link) {
+                self.extra_links.0.push(link);
+            }
+        }
+        self.update_config_links()
+    }
+
+    fn remove_link(&mut self, link: config::NamedLink) -> Result<(), config::Error> {
+        self.remove_links(config::expand_links(config::NamedLinks(vec![link]))?)
+    }
+
+    // Without brace expansion
+    fn remove_links(&mut self, links: config::NamedLinks) -> Result<(), config::Error> {
+        for link in links.0 {
+            self.extra_links.0.retain(|extra| *extra != link);
+            if !self.removed_links.0.contains(&link) {
+                self.removed_links.0.push(link);
+            }
+        }
+        self.update_config_links()
+    }
+
+    fn toggle_link(&mut self, link: config::NamedLink) -> Result<(), config::Error> {
+        let links = config::expand_links(config::NamedLinks(vec![link.clone()]))?;
+        if links.0.iter().all(|link| self.config_links.0.contains(link)) {
+            self.remove_link(link)
+        } else {
+            self.add_link(link)
+        }
+    }
+
+    fn set_profile(&mut self, profile: Option<String>) -> Result<(), config::Error> {
+        self.config.profile_links(profile.as_deref())?;
+        self.profile = profile;
+        self.update_config_links()
+    }
+
+    // Reads the config file again, dropping the changes made at runtime
+    fn reload(&mut self) -> Result<(), config::Error> {
+        let filename = match &self.config_filename {
+            Some(filename) => filename,
+            None => return Ok(()),
+        };
+        let config = config::Config::load(filename, self.config_format)?;
+        if !self.profile.as_ref().is_some_and(|profile| config.profiles.contains_key(profile)) {
+            self.profile = config.profile.clone();
+        }
+        self.config = config;
+        self.extra_links = config::NamedLinks::default();
+        self.removed_links = config::NamedLinks::default();
+        self.update_config_links()
+    }
+
+    // Writes the running state of the managed nodes to the config file and reloads it
+    fn save(&mut self) -> Result<(), config::Error> {
+        let Some(filename) = self.config_filename.clone() else {
+            return Ok(());
+        };
+        let format = self.config_format.unwrap_or_else(|| config::ConfigFormat::from_filename(&filename));
+        let changes = self.save_changes()?;
+        let contents = std::fs::read_to_string(&filename)?;
+        config::write_file(&filename, &save::apply(&contents, &filename, format, &changes)?)?;
+        info!(added = changes.add.len(), removed = changes.remove.len(), "Saved {filename}");
+        self.reload()
+    }
+
+    // The links of the graph on the nodes of configured ports and those added at runtime are
+    // added; the common links removed at runtime or not made although possible are removed
+    fn save_changes(&self) -> Result<save::Changes, config::Error> {
+        let configured = self.config.profile_links(self.profile.as_deref())?;
+        let graph_links = self.graph_links();
+        let ends = |link: &config::NamedLink| {
+            [self.find_port(&link.src, PortDirection::Out), self.find_port(&link.dst, PortDirection::In)]
+        };
+        let managed: HashSet<&NodeId> = configured.0.iter()
+            .flat_map(|link| ends(link).into_iter().flatten().map(|port| &port.node_id))
+            .collect();
+        let mut add: Vec<_> = graph_links.iter()
+            .filter(|link| ends(link).iter().flatten().any(|port| managed.contains(&port.node_id)))
+            .chain(self.extra_links.0.iter())
+            .filter(|link| !configured.0.contains(link))
+            .cloned()
+            .collect();
+        add.sort();
+        add.dedup();
+        let remove = self.config.links.0.iter()
+            .filter(|link| !graph_links.contains(link))
+            .filter(|link| {
+                let possible = ends(link).iter().all(Option::is_some)
+                    && !matches!(self.link_states.get(link).map(|status| &status.state),
+                                 Some(status::LinkState::Failed(_) | status::LinkState::Pending));
+                self.removed_links.0.contains(link) || possible
+            })
+            .cloned()
+            .collect();
+        Ok(save::Changes { add, remove })
+    }
+
+    // Where a desired link comes from, for the logs
+    fn origin(&self, link: &config::NamedLink) -> String {
+        let profile = self.profile.as_ref()
+            .filter(|profile| self.config.profiles.get(*profile).is_some_and(|profile| profile.links.0.contains(link)));
+        match &self.mode {
+            Mode::Link { .. } => "command line".to_string(),
+            Mode::Restore(_) => "snapshot".to_string(),
+            _ if self.extra_links.0.contains(link) => "runtime".to_string(),
+            _ if self.config.links.0.contains(link) => "links".to_string(),
+            _ => match profile {
+                Some(profile) => format!("profile {profile}"),
+                None => "unknown".to_string(),
+            },
+        }
+    }
+
+    // The commands of the config for the event; only when the graph is ours to change
+    fn run_hooks(&self, trigger: hooks::Trigger) {
+        if !self.mode.read_only() {
+            (self.hook_runner)(&self.config.hooks, &trigger);
+        }
+    }
+
+    // Remembers why the link is about to change, for the audit log
+    fn audit_reason(&mut self, link: &config::NamedLink, reason: audit::Reason) {
+        if self.audit_log.is_some() {
+            self.audit_reasons.insert(link.clone(), reason);
+        }
+    }
+
+    // Appends the change to the audit log
+    fn audit(&mut self, action: audit::Action, reason: audit::Reason, link: &config::NamedLink, link_id: Option<&LinkObjectId>) {
+        let Some(mut audit_log) = self.audit_log.take() else {
+            return;
+        };
+        let entry = audit::Entry {
+            timestamp: audit::Entry::now(),
+            action,
+            reason,
+            rule: (action == audit::Action::Create).then(|| self.origin(link)),
+            src: audit::End::new(&link.src, self.find_port_entry(&link.src, PortDirection::Out)),
+            dst: audit::End::new(&link.dst, self.find_port_entry(&link.dst, PortDirection::In)),
+            link_id: link_id.map(|link_id| link_id.0.as_str()),
+        };
+        if let Err(error) = audit_log.write(&entry) {
+            warn!(%error, "Failed to write the audit log");
+        }
+        self.audit_log = Some(audit_log);
+    }
+
+    // Links added at runtime may have their own policy
+    fn on_exit(&self, link: &config::NamedLink) -> config::OnExit {
+        match self.extra_links.0.iter().find(|extra| *extra == link).and_then(|extra| extra.on_exit) {
+            Some(on_exit) => on_exit,
+            None => self.config.on_exit(link),
+        }
+    }
+
+    // The links of the graph by port names
+    fn graph_links(&self) -> Vec<config::NamedLink> {
+        self.links.keys()
+            .filter_map(|key| self.port_names(key))
+            .map(|(src, dst)| config::NamedLink { src, dst, on_exit: None })
+            .collect()
+    }
+
+    fn find_port(&self, name: &PortName, direction: PortDirection) -> Option<&Port> {
+        self.find_port_entry(name, direction).map(|(_, port)| port)
+    }
+
+    // The port with its registry id
+    fn find_port_entry(&self, name: &PortName, direction: PortDirection) -> Option<(&str, &Port)> {
+        self.ports.iter()
+            .find(|(_, port)| port.port_name == *name && port.port_direction == direction)
+            .map(|(port_id, port)| (port_id.0.as_str(), port))
+    }
+
+    fn diff(&self) -> diff::Diff {
+        let graph_links = self.graph_links();
+        let mut diff = diff::Diff::default();
+        for link in self.config_links.0.iter() {
+            if graph_links.contains(link) {
+                continue;
+            }
+            match (self.find_port(&link.src, PortDirection::Out), self.find_port(&link.dst, PortDirection::In)) {
+                (Some(_), Some(_)) => diff.missing.push(link.clone()),
+                _ => diff.impossible.push(link.clone()),
+            }
         }
+        diff.unconfigured = graph_links.into_iter()
+            .filter(|link| !self.config_links.0.contains(link))
+            .collect();
+        diff.unconfigured.sort();
+        diff
+    }
+
+    // The graph with the configured links, present or missing
+    fn dot(&self, filter: &dump::Filter) -> String {
+        let ports: Vec<_> = self.ports.values()
+            .map(|port| dot::DotPort {
+                node: self.nodes.get(&port.node_id),
+                port_name: &port.port_name,
+                name: &port.name,
+                direction: port.port_direction.clone(),
+            })
+            .collect();
+        let graph_links = self.graph_links();
+        let mut links: Vec<_> = graph_links.iter()
+            .map(|link| {
+                let edge = if self.config_links.0.contains(link) { dot::Edge::Managed } else { dot::Edge::Unmanaged };
+                (self.graph_link(link.clone()), edge)
+            })
+            .collect();
+        links.extend(self.diff().missing.into_iter()
+            .map(|link| (self.graph_link(link), dot::Edge::Missing)));
+        dot::dot(&ports, &links, filter)
+    }
+
+    // The configured links if there is a config, otherwise those of the graph, for other tools
+    fn export(&self, options: &dump::DumpOptions) -> String {
+        let links = if self.config_filename.is_some() { self.config_links.0.clone() } else { self.graph_links() };
+        let mut links: Vec<_> = links.into_iter()
+            .map(|link| self.graph_link(link))
+            .filter(|link| options.filter.matches(link))
+            .map(|link| link.link)
+            .collect();
+        links.sort();
+        if options.format == Format::PwLinkScript {
+            return dump::pw_link_script(&links);
+        }
+        let items: Vec<_> = links.iter()
+            .map(|link| {
+                let src = self.find_port(&link.src, PortDirection::Out);
+                let dst = self.find_port(&link.dst, PortDirection::In);
+                let media_type = src.or(dst).and_then(|port| port.media_type.as_deref()).unwrap_or_default();
+                dump::PatchbayItem {
+                    port_type: if media_type.contains("midi") {
+                        "pipewire-midi"
+                    } else if media_type.contains("video") {
+                        "pipewire-video"
+                    } else {
+                        "pipewire-audio"
+                    },
+                    output: self.patchbay_port(&link.src, src),
+                    input: self.patchbay_port(&link.dst, dst),
+                }
+            })
+            .collect();
+        dump::qpwgraph(&items)
+    }
+
+    // qpwgraph names ports by node and port name; absent ports are split at the first colon
+    fn patchbay_port(&self, name: &PortName, port: Option<&Port>) -> (String, String) {
+        match port {
+            Some(port) => {
+                let node = self.nodes.get(&port.node_id)
+                    .map(|node| node.description.clone().unwrap_or(node.name.clone()))
+                    .unwrap_or_default();
+                (node, port.name.clone())
+            }
+            None => match name.0.split_once(':') {
+                Some((node, port)) => (node.to_string(), port.to_string()),
+                None => (String::new(), name.0.clone()),
+            },
+        }
+    }
+
+    // The name of a port given by alias, or by node name or description and port name
+    fn resolve_port(&self, name: &PortName, direction: PortDirection) -> Option<PortName> {
+        let port = self.ports.values()
+            .filter(|port| port.port_direction == direction)
+            .find(|port| {
+                port.alias.as_ref() == Some(&name.0) || self.nodes.get(&port.node_id).is_some_and(|node| {
+                    let node_names = std::iter::once(&node.name).chain(node.description.iter());
+                    node_names.map(|node_name| format!("{node_name}:{}", port.name)).any(|full_name| full_name == name.0)
+                })
+            })?;
+        Some(port.port_name.clone())
+    }
+
+    fn graph_link(&self, link: config::NamedLink) -> dump::GraphLink<'_> {
+        let node = |name, direction| {
+            self.find_port(name, direction).and_then(|port| self.nodes.get(&port.node_id))
+        };
+        dump::GraphLink {
+            src_node: node(&link.src, PortDirection::Out),
+            dst_node: node(&link.dst, PortDirection::In),
+            link,
+        }
+    }
+
+    fn port_names(&self, key: &(PortObjectId<Output>, PortObjectId<Input>)) -> Option<(PortName, PortName)> {
+        let src = self.ports.get(&key.0.clone().unknown())?;
+        let dst = self.ports.get(&key.1.clone().unknown())?;
+        Some((src.port_name.clone(), dst.port_name.clone()))
+    }
+
+    fn set_link_state(&mut self, link: config::NamedLink, state: status::LinkState) {
+        self.link_states.entry(link).or_default().set(state);
     }
 
     fn process_message(&mut self, message: Message) {
         match message {
             Message::Object(object) => {
                 let props = &object.properties;
-                if let (Some(port_name), Some(node_id), Some(port_id), Some(port_direction)) = (
+                if let (Some(name), Some(node_id), Some(port_id), Some(port_direction)) = (
                     props.get("port.name"),
                     props.get("node.id"),
                     props.get("port.id"),
                     props.get("port.direction"),
                 ) {
-                    let mut port_name = PortName(port_name.clone());
+                    let mut port_name = PortName(name.clone());
                     if let Some(port_alias) = props.get("port.alias") {
                         port_name = PortName(port_alias.clone())
                     }
@@ -258,9 +950,24 @@ impl Main {
                         port_name,
                         port_id,
                         port_direction,
+                        name: name.clone(),
+                        alias: props.get("port.alias").cloned(),
+                        media_type: props.get("format.dsp").cloned(),
+                        object_path: props.get("object.path").cloned(),
                     };
-                    // dbg!(&key, &port);
-                    // dbg!(&object);
+                    debug!(port = %port.port_name.0, port_id = %port.port_id.0, node_id = %port.node_id.0, "Port appeared");
+                    self.subscribers.send(events::Event::PortAppeared { port: port.port_name.clone() });
+                    let new = self.seen_ports.insert(port.port_name.clone());
+                    if self.graph_synced {
+                        let reason = if new { audit::Reason::PortAppeared } else { audit::Reason::DeviceReappeared };
+                        let links: Vec<_> = self.config_links.0.iter()
+                            .filter(|link| link.src == port.port_name || link.dst == port.port_name)
+                            .cloned()
+                            .collect();
+                        for link in links {
+                            self.audit_reason(&link, reason);
+                        }
+                    }
                     assert!(matches!(self.ports.insert(key, port), None));
                 } else if let (
                     Some(link_output_port),
@@ -290,33 +997,105 @@ impl Main {
                         link_output_port,
                     };
 
-                    //dbg!(&link);
-
                     e.or_default().push(link);
 
+                    if let Some((src, dst)) = self.port_names(&key) {
+                        debug!(src = %src.0, dst = %dst.0, link_id = %object.id.0, "Link created");
+                        let link = config::NamedLink { src, dst, on_exit: None };
+                        if let Some((linger, reason)) = self.audit_pending.remove(&link) {
+                            let link_id = LinkObjectId::from(object.id.clone());
+                            self.audit(audit::Action::Create, reason, &link, Some(&link_id));
+                            if !linger {
+                                self.audit_owned.insert(link.clone(), link_id);
+                            }
+                        }
+                        if self.config_links.0.contains(&link) {
+                            self.run_hooks(hooks::Trigger::Link(config::HookEvent::LinkCreated, &link));
+                        }
+                        self.subscribers.send(events::Event::LinkCreated { src: link.src, dst: link.dst });
+                    }
+
                     assert!(matches!(
                         self.links_by_id.insert(object.id.into(), key),
                         None
                     ));
+                } else if let Some(node_name) = props.get("node.name") {
+                    let node = Node {
+                        name: node_name.clone(),
+                        description: props.get("node.description").cloned(),
+                    };
+                    // not for the nodes there at start, or again after reconnecting
+                    if self.graph_synced {
+                        self.run_hooks(hooks::Trigger::Device(&node));
+                    }
+                    self.nodes.insert(NodeId(object.id.0), node);
                 } else {
                     //println!("got {object:?}");
                 }
             }
             Message::Remove(id) => {
-                // try to remove objects from both sets
-                self.ports.remove(&id.clone().into());
+                // try to remove objects from all sets
+                self.nodes.remove(&NodeId(id.0.clone()));
+                if let Some(port) = self.ports.remove(&id.clone().into()) {
+                    self.subscribers.send(events::Event::PortRemoved { port: port.port_name });
+                }
                 if let Some(key) = self.links_by_id.remove(&id.into()) {
                     self.links.remove(&key);
+                    if let Some((src, dst)) = self.port_names(&key) {
+                        let link = config::NamedLink { src, dst, on_exit: None };
+                        self.audit_owned.remove(&link);
+                        if self.config_links.0.contains(&link) {
+                            info!(src = %link.src.0, dst = %link.dst.0, origin = %self.origin(&link), "Configured link lost; relinking");
+                            self.audit_reason(&link, audit::Reason::Enforcement);
+                            self.run_hooks(hooks::Trigger::Link(config::HookEvent::LinkLost, &link));
+                        } else {
+                            debug!(src = %link.src.0, dst = %link.dst.0, "Link lost");
+                        }
+                        self.subscribers.send(events::Event::LinkLost { src: link.src, dst: link.dst });
+                    }
+                }
+            }
+            Message::LinkFailed(link, error) => {
+                warn!(src = %link.src.0, dst = %link.dst.0, %error, "Failed to link; retrying when the ports come back");
+                // nothing was made
+                self.audit_pending.remove(&link);
+                self.audit_reasons.remove(&link);
+                self.set_link_state(link, status::LinkState::Failed(error));
+            }
+            Message::Destroyed(link_id) => {
+                if let Some(link) = self.pending_destroys.remove(&link_id) {
+                    // its removal would have come first
+                    if self.links_by_id.contains_key(&link_id) {
+                        warn!(src = %link.src.0, dst = %link.dst.0, link_id = %link_id.0, "Link still there after unlinking");
+                        if !self.failed_unlinks.contains(&link) {
+                            self.failed_unlinks.push(link);
+                        }
+                    }
+                }
+            }
+            Message::Shutdown => (),
+            Message::Midi(midi_message) => {
+                let triggers = self.config.midi.iter().flat_map(|midi| midi.triggers.iter());
+                let triggers: Vec<_> = triggers.filter(|trigger| midi::trigger_matches(trigger, &midi_message))
+                    .cloned().collect();
+                for trigger in triggers {
+                    info!(message = ?midi_message, "MIDI trigger");
+                    let result = match (trigger.profile, trigger.toggle) {
+                        (Some(profile), _) => self.set_profile(Some(profile)),
+                        (_, Some(link)) => self.toggle_link(link),
+                        (None, None) => Ok(()),
+                    };
+                    if let Err(error) = result {
+                        warn!(%error, "MIDI trigger failed");
+                    }
                 }
             }
         }
     }
 
-    fn control_thread(&mut self,
+    fn control_thread(main: Arc<Mutex<Main>>,
 		      rx: Receiver<Message>,
 		      tx: Arc<Mutex<pw::channel::Sender<PWRequest>>>) {
-        let mut stable; // seems things are settled, no messages in a short while
-        #[allow(unused_mut)] let mut enable_dump = false;
 	let mut processing = true;
         while processing {
             let message = match rx.recv_timeout(time::Duration::from_millis(100)) {
@@ -327,80 +1106,137 @@ impl Main {
 		    None
 		}
             };
-            if let Some(message) = message {
-                if enable_dump {
-                    //dbg!(&message);
-                }
-                self.process_message(message);
-                stable = false;
-            } else {
-                stable = true;
-		if self.dump_and_exit {
-		    {
-			let tx = tx.lock().expect("Failed to lock TX");
-			// could be exiting already
-			let _ignore = tx.send(PWRequest::Quit(QuitReason::Done));
-		    }
-		    processing = false;
-		    let mut links = config::NamedLinks::default();
-		    for link in &self.links {
-			let src = self.ports.get(&link.0.0.clone().unknown()).map(|x| x.port_name.clone());
-			let dst = self.ports.get(&link.0.1.clone().unknown()).map(|x| x.port_name.clone());
-			if let (Some(src), Some(dst)) = (src, dst) {
-			    links.0.push(config::NamedLink { src, dst });
-			}
-		    }
-		    links.0.sort();
-		    let config = config::Config { links };
-		    config.dump();
-		}
+            let mut main = main.lock().expect("Failed to lock main");
+            processing = main.step(message, processing, &tx);
+        }
+    }
+
+    // Processes one message, or reconciles the links if there was none; returns whether to keep processing
+    fn step(&mut self,
+	    message: Option<Message>,
+	    mut processing: bool,
+	    tx: &Arc<Mutex<pw::channel::Sender<PWRequest>>>) -> bool {
+        let stable; // seems things are settled, no messages in a short while
+        #[allow(unused_mut)] let mut enable_dump = false;
+        if self.shutdown_requested || matches!(message, Some(Message::Shutdown)) {
+            self.shutdown(tx);
+            return false;
+        }
+        if let Some(message) = message {
+            if enable_dump {
+                //dbg!(&message);
+            }
+            self.process_message(message);
+            stable = false;
+        } else {
+            stable = true;
+            if let Mode::Dump(options) = &self.mode {
+                {
+                    let tx = tx.lock().expect("Failed to lock TX");
+                    // could be exiting already
+                    let _ignore = tx.send(PWRequest::Quit(QuitReason::Done));
+                }
+                processing = false;
+                if options.format == Format::Dot {
+                    print!("{}", self.dot(&options.filter));
+                } else if matches!(options.format, Format::Qpwgraph | Format::PwLinkScript) {
+                    print!("{}", self.export(options));
+                } else {
+                    let links = self.links.keys()
+                        .filter_map(|key| {
+                            let src = self.ports.get(&key.0.clone().unknown())?;
+                            let dst = self.ports.get(&key.1.clone().unknown())?;
+                            Some(dump::GraphLink {
+                                link: config::NamedLink { src: src.port_name.clone(), dst: dst.port_name.clone(), on_exit: None },
+                                src_node: self.nodes.get(&src.node_id),
+                                dst_node: self.nodes.get(&dst.node_id),
+                            })
+                        })
+                        .collect();
+                    print!("{}", dump::dump(links, options));
+                }
             }
+        }
 
-	    if processing {
-		let mut name_dir_input_port_id: HashMap<PortName, PortObjectId<Input>> = HashMap::new();
-
-		let mut name_dir_output_port_id: HashMap<PortName, PortObjectId<Output>> =
-                    HashMap::new();
-
-		// TODO: maintain in self.process_message
-		// TODO: deal with multiple ports labeled the same
-		// dbg!(());
-		for (port_id, port) in self.ports.iter() {
-                    //dbg!(port_id, port);
-                    match port.port_direction {
-			PortDirection::In => {
-                            name_dir_input_port_id
-				.insert(port.port_name.clone(), port_id.clone().input());
-			}
-			PortDirection::Out => {
-                            name_dir_output_port_id
-				.insert(port.port_name.clone(), port_id.clone().output());
-			}
+        if processing {
+            let mut name_dir_input_port_id: HashMap<PortName, PortObjectId<Input>> = HashMap::new();
+
+            let mut name_dir_output_port_id: HashMap<PortName, PortObjectId<Output>> =
+                HashMap::new();
+
+            // TODO: maintain in self.process_message
+            // TODO: deal with multiple ports labeled the same
+            // dbg!(());
+            for (port_id, port) in self.ports.iter() {
+                //dbg!(port_id, port);
+                match port.port_direction {
+                    PortDirection::In => {
+                        name_dir_input_port_id
+                            .insert(port.port_name.clone(), port_id.clone().input());
                     }
-		}
+                    PortDirection::Out => {
+                        name_dir_output_port_id
+                            .insert(port.port_name.clone(), port_id.clone().output());
+                    }
+                }
+            }
 
-		if stable {
-                    for named_link in self.config_links.0.clone().iter() {
-			self.do_link(
-                            &name_dir_input_port_id,
-                            &name_dir_output_port_id,
-                            tx.clone(),
-                            &named_link.src,
-                            &named_link.dst,
-			);
+            if stable {
+                self.graph_synced = true;
+                if self.initial_links.is_none() {
+                    self.initial_links = Some(self.graph_links());
+                    if let Mode::Restore(_) = self.mode {
+                        self.resolve_snapshot();
                     }
-		}
-	    }
+                }
+                let mut plan = plan::Plan::default();
+                let mut not_linked = vec![];
+                for named_link in std::mem::take(&mut self.pending_unlinks) {
+                    if !self.plan_unlink(
+                        &name_dir_input_port_id,
+                        &name_dir_output_port_id,
+                        &named_link,
+                        &mut plan,
+                    ) {
+                        not_linked.push(named_link);
+                    }
+                }
+                for named_link in self.config_links.0.clone().iter() {
+                    self.plan_link(
+                        &name_dir_input_port_id,
+                        &name_dir_output_port_id,
+                        &named_link.src,
+                        &named_link.dst,
+                        &mut plan,
+                    );
+                }
+                if !self.mode.read_only() {
+                    self.apply(
+                        &name_dir_input_port_id,
+                        &name_dir_output_port_id,
+                        tx,
+                        &plan,
+                    );
+                }
+                if let Some(quit_reason) = self.finished(&plan, &not_linked).or_else(|| self.timed_out()) {
+                    let tx = tx.lock().expect("Failed to lock TX");
+                    // could be exiting already
+                    let _ignore = tx.send(PWRequest::Quit(quit_reason));
+                    processing = false;
+                }
+            }
         }
+        processing
     }
 
-    fn do_link(
+    // Decides whether the link needs to be made, or why it cannot be
+    fn plan_link(
         &mut self,
         name_dir_input_port_id: &HashMap<PortName, PortObjectId<Input>>,
         name_dir_output_port_id: &HashMap<PortName, PortObjectId<Output>>,
-        tx: Arc<Mutex<pw::channel::Sender<PWRequest>>>,
         src_name: &PortName,
         dst_name: &PortName,
+        plan: &mut plan::Plan,
     ) {
         let src_port_id = name_dir_output_port_id.get(src_name);
         let dst_port_id = name_dir_input_port_id.get(dst_name);
@@ -416,49 +1252,433 @@ impl Main {
             None
         };
 
+        let named_link = config::NamedLink { src: src_name.clone(), dst: dst_name.clone(), on_exit: None };
         match has_link {
             None => {
                 // enable_dump = true;
 		let pair = (src_name.clone(), dst_name.clone());
-                if let (Some(src_port_id), Some(dst_port_id)) = (src_port_id, dst_port_id) {
-                    let src_port = self
-                        .ports
-                        .get(&src_port_id.clone().unknown())
-                        .expect("could not find port by id")
-                        .clone();
-                    let dst_port = self
-                        .ports
-                        .get(&dst_port_id.clone().unknown())
-                        .expect("could not find port by id")
-                        .clone();
-                    eprintln!(
-                        "link \"{}\" -> \"{}\"",
-                        src_port.port_name.0, &dst_port.port_name.0
-                    );
-                    //println!("link {src_port:?} -> {dst_port:?}",);
-		    {
-			let tx = tx.lock().expect("Failed to lock tx");
-
-			// could be exiting already
-			let _ignore = tx.send(PWRequest::MakeLink((src_port, dst_port)));
-		    }
-		    self.failed_pairs.remove(&pair);
-                } else if !self.failed_pairs.contains(&pair) {
-		    eprintln!(
-                        "Cannot link \"{}\" -> \"{}\", both ports not found",
-                        src_name.0, dst_name.0
-		    );
-		    self.failed_pairs.insert(pair);
+                let failed = matches!(self.link_states.get(&named_link),
+                                      Some(status::LinkStatus { state: status::LinkState::Failed(_), .. }));
+                if let (Some(_), Some(_), true) = (src_port_id, dst_port_id, failed) {
+                    // don't retry until the ports come back
+                    let state = self.link_states[&named_link].state.clone();
+                    plan.unresolved.push(plan::Unresolved { link: named_link, state });
+                } else if let (Some(_), Some(_)) = (src_port_id, dst_port_id) {
+                    plan.create.push(named_link);
+                } else {
+                    if !self.failed_pairs.contains(&pair) {
+		        info!(
+                            src = %src_name.0, dst = %dst_name.0, origin = %self.origin(&named_link),
+                            "Cannot link yet, both ports not found; retrying when they appear"
+		        );
+		        self.failed_pairs.insert(pair);
+                        self.run_hooks(hooks::Trigger::Link(config::HookEvent::LinkUnresolved, &named_link));
+                    }
+                    let state = if src_port_id.is_none() {
+                        status::LinkState::WaitingForSrc
+                    } else {
+                        status::LinkState::WaitingForDst
+                    };
+                    plan.unresolved.push(plan::Unresolved { link: named_link.clone(), state: state.clone() });
+                    self.set_link_state(named_link, state);
                 }
             }
             Some(_link) => {
                 //eprintln!("Already linked: {link:?}")
+                self.set_link_state(named_link, status::LinkState::Linked);
+            }
+        }
+    }
+
+    // Puts back the initial links we removed for the links that want that, then quits
+    fn shutdown(&mut self, tx: &Arc<Mutex<pw::channel::Sender<PWRequest>>>) {
+        let tx = tx.lock().expect("Failed to lock tx");
+        if let Mode::Learn = self.mode {
+            let reason = match self.save_learned() {
+                Ok(()) => QuitReason::Done,
+                Err(error) => QuitReason::Failed(format!("Failed to save the config: {error}")),
+            };
+            // could be exiting already
+            let _ignore = tx.send(PWRequest::Quit(reason));
+            return;
+        }
+        // the links made by us go away with our connection
+        let mut owned: Vec<_> = self.audit_owned.drain().collect();
+        owned.sort_by(|(_, left), (_, right)| left.0.cmp(&right.0));
+        for (link, link_id) in owned {
+            self.audit(audit::Action::Destroy, audit::Reason::Exit, &link, Some(&link_id));
+        }
+        let graph_links = self.graph_links();
+        // only the modes that change the graph have anything to put back
+        let initial_links = if self.mode.read_only() { None } else { self.initial_links.clone() };
+        // the links found at start are mostly not configured, so for them the global policy decides
+        for link in initial_links.iter().flatten() {
+            if graph_links.contains(link) || self.on_exit(link) != config::OnExit::Restore {
+                continue;
+            }
+            match (self.find_port(&link.src, PortDirection::Out), self.find_port(&link.dst, PortDirection::In)) {
+                (Some(src_port), Some(dst_port)) => {
+                    info!(src = %link.src.0, dst = %link.dst.0, "Restoring link");
+                    let ports = Box::new((src_port.clone(), dst_port.clone()));
+                    // written now, as it shows up only after we're gone
+                    self.audit(audit::Action::Create, audit::Reason::Exit, link, None);
+                    // could be exiting already
+                    let _ignore = tx.send(PWRequest::MakeLink(ports, true));
+                }
+                _ => warn!(src = %link.src.0, dst = %link.dst.0, "Cannot restore link, both ports not found"),
             }
         }
+        // could be exiting already
+        let _ignore = tx.send(PWRequest::Quit(QuitReason::Done));
+    }
+
+    // Adds the links made since learning started to the common links of the config file and drops
+    // those removed from them; the profiles and the rest of the file are left as they are
+    fn save_learned(&self) -> Result<(), config::Error> {
+        let (Some(filename), Some(initial_links)) = (&self.config_filename, &self.initial_links) else {
+            info!("Nothing learned");
+            return Ok(());
+        };
+        let format = self.config_format.unwrap_or_else(|| config::ConfigFormat::from_filename(filename));
+        let graph_links = self.graph_links();
+        let remove: Vec<_> = self.config.links.0.iter()
+            .filter(|link| initial_links.contains(link) && !graph_links.contains(link))
+            .cloned()
+            .collect();
+        let mut add: Vec<_> = graph_links.into_iter()
+            .filter(|link| !initial_links.contains(link) && !self.config.links.0.contains(link))
+            .collect();
+        add.sort();
+        info!("Learned {} links, dropped {}", add.len(), remove.len());
+        let contents = match std::fs::read_to_string(filename) {
+            Ok(contents) => contents,
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => config::Config::default().dump(format),
+            Err(error) => return Err(error.into()),
+        };
+        let changes = save::Changes { add, remove };
+        config::write_file(filename, &save::apply(&contents, filename, format, &changes)?)
+    }
+
+    // The links of the graph with their ports in full
+    fn snapshot(&self) -> snapshot::Snapshot {
+        let identity = |port: &Port| snapshot::PortIdentity::of(port, self.nodes.get(&port.node_id));
+        let mut links: Vec<_> = self.links.keys()
+            .filter_map(|(src, dst)| {
+                let src = self.ports.get(&src.clone().unknown())?;
+                let dst = self.ports.get(&dst.clone().unknown())?;
+                Some(snapshot::SnapshotLink { src: identity(src), dst: identity(dst) })
+            })
+            .collect();
+        links.sort_by(|a, b| (&a.src.node, &a.src.port, &a.dst.node, &a.dst.port).cmp(&(&b.src.node, &b.src.port, &b.dst.node, &b.dst.port)));
+        snapshot::Snapshot { links }
+    }
+
+    // Turns the links of the snapshot into desired links by the current names of their ports
+    fn resolve_snapshot(&mut self) {
+        let Mode::Restore(restore) = &self.mode else {
+            return;
+        };
+        let candidates = |direction: PortDirection| -> (Vec<&Port>, Vec<snapshot::PortIdentity>) {
+            self.ports.values()
+                .filter(|port| port.port_direction == direction)
+                .map(|port| (port, snapshot::PortIdentity::of(port, self.nodes.get(&port.node_id))))
+                .unzip()
+        };
+        let (outputs, output_identities) = candidates(PortDirection::Out);
+        let (inputs, input_identities) = candidates(PortDirection::In);
+        let mut links = config::NamedLinks::default();
+        let mut missing = 0;
+        for link in restore.snapshot.links.iter() {
+            let src = snapshot::find(&link.src, &output_identities).map(|index| outputs[index]);
+            let dst = snapshot::find(&link.dst, &input_identities).map(|index| inputs[index]);
+            match (src, dst) {
+                (Some(src), Some(dst)) => links.0.push(config::NamedLink {
+                    src: src.port_name.clone(),
+                    dst: dst.port_name.clone(),
+                    on_exit: None,
+                }),
+                _ => {
+                    let name = |identity: &snapshot::PortIdentity| identity.alias.clone().unwrap_or(identity.port.clone());
+                    warn!(src = %name(&link.src), dst = %name(&link.dst), "Cannot restore link, both ports not found");
+                    missing += 1;
+                }
+            }
+        }
+        let pruned: Vec<_> = if restore.prune {
+            self.graph_links().into_iter().filter(|link| !links.0.contains(link)).collect()
+        } else {
+            vec![]
+        };
+        if let Mode::Restore(restore) = &mut self.mode {
+            restore.missing = missing;
+            restore.pruned = pruned.clone();
+        }
+        for link in links.0.iter() {
+            self.link_states.entry(link.clone()).or_default();
+        }
+        self.config_links = links;
+        self.pending_unlinks = pruned;
+    }
+
+    // Whether the job of a one-shot run is done, after reconciling the links
+    fn finished(&self, plan: &plan::Plan, not_linked: &[config::NamedLink]) -> Option<QuitReason> {
+        match &self.mode {
+            Mode::Daemon | Mode::Dump(_) | Mode::Learn | Mode::Monitor(_) => None,
+            Mode::ListPorts(options) => {
+                let mut peers: HashMap<&PortObjectId<Unknown>, Vec<String>> = HashMap::new();
+                for (src, dst) in self.links.keys() {
+                    let (src, dst) = (src.clone().unknown(), dst.clone().unknown());
+                    if let (Some((src, src_port)), Some((dst, dst_port))) =
+                        (self.ports.get_key_value(&src), self.ports.get_key_value(&dst)) {
+                        peers.entry(src).or_default().push(dst_port.port_name.0.clone());
+                        peers.entry(dst).or_default().push(src_port.port_name.0.clone());
+                    }
+                }
+                let mut entries: Vec<_> = self.ports.iter()
+                    .map(|(port_id, port)| {
+                        let node = self.nodes.get(&port.node_id);
+                        let mut links = peers.remove(port_id).unwrap_or_default();
+                        links.sort();
+                        list::PortEntry {
+                            port: port.port_name.0.clone(),
+                            name: port.name.clone(),
+                            alias: port.alias.clone(),
+                            direction: match port.port_direction {
+                                PortDirection::In => "in",
+                                PortDirection::Out => "out",
+                            },
+                            node: node.map(|node| node.name.clone()),
+                            node_description: node.and_then(|node| node.description.clone()),
+                            media_type: port.media_type.clone(),
+                            links,
+                        }
+                    })
+                    .filter(|entry| options.matches(entry))
+                    .collect();
+                entries.sort_by(|a, b| (&a.node, a.direction, &a.port).cmp(&(&b.node, b.direction, &b.port)));
+                match options.format {
+                    Format::Json => println!("{}", serde_json::to_string_pretty(&entries).expect("Failed to serialize json")),
+                    _ => print!("{}", list::table(&entries)),
+                }
+                Some(QuitReason::Done)
+            }
+            Mode::Import(import, format) => {
+                let mut import = import.clone();
+                import.resolve(|name, direction| self.resolve_port(name, direction));
+                for name in import.unresolved.iter() {
+                    warn!(port = %name.0, "Port not found, keeping its name");
+                }
+                let links = import.compressed().links;
+                print!("{}", config::Config { links, ..config::Config::default() }.dump(*format));
+                Some(QuitReason::Done)
+            }
+            Mode::Diff => {
+                let config_name = self.config_filename.as_deref().unwrap_or("config");
+                print!("{}", self.diff().unified(config_name));
+                Some(QuitReason::Done)
+            }
+            Mode::DryRun(format) => {
+                match format {
+                    Format::Json => println!("{}", serde_json::to_string_pretty(plan).expect("Failed to serialize json")),
+                    _ => print!("{plan}"),
+                }
+                Some(QuitReason::Done)
+            }
+            Mode::SnapshotSave(filename) => {
+                let snapshot = self.snapshot();
+                match snapshot.save(filename) {
+                    Ok(()) => {
+                        info!("Saved {} links to {filename}", snapshot.links.len());
+                        Some(QuitReason::Done)
+                    }
+                    Err(error) => Some(QuitReason::Failed(format!("Failed to save {filename}: {error}"))),
+                }
+            }
+            Mode::Restore(restore) => {
+                let states = self.config_links.0.iter()
+                    .filter_map(|link| self.link_states.get(link).map(|link_status| &link_status.state));
+                let (mut linked, mut failed) = (0, 0);
+                for state in states {
+                    match state {
+                        status::LinkState::Linked => linked += 1,
+                        status::LinkState::Pending => (),
+                        _ => failed += 1,
+                    }
+                }
+                let graph_links = self.graph_links();
+                let pruned = restore.pruned.iter().all(|link| !graph_links.contains(link));
+                let total = restore.snapshot.links.len();
+                if failed == 0 && (linked < self.config_links.0.len() || !pruned) {
+                    None
+                } else if failed + restore.missing > 0 {
+                    Some(QuitReason::Failed(format!("{} of {total} links could not be restored", failed + restore.missing)))
+                } else {
+                    Some(QuitReason::Done)
+                }
+            }
+            Mode::Link { linger } => {
+                let states = self.config_links.0.iter()
+                    .filter_map(|link| self.link_states.get(link).map(|link_status| &link_status.state));
+                let (mut linked, mut failed) = (0, 0);
+                for state in states {
+                    match state {
+                        status::LinkState::Linked => linked += 1,
+                        status::LinkState::Pending => (),
+                        _ => failed += 1,
+                    }
+                }
+                if failed > 0 {
+                    Some(QuitReason::Failed(format!("{failed} of {} links could not be made", self.config_links.0.len())))
+                } else if *linger && linked == self.config_links.0.len() {
+                    Some(QuitReason::Done)
+                } else {
+                    None
+                }
+            }
+            Mode::Once { timeout } => {
+                let states: Vec<_> = self.config_links.0.iter()
+                    .map(|link| (link, self.link_states.get(link).map(|link_status| &link_status.state)))
+                    .collect();
+                if states.iter().any(|(_, state)| matches!(state, Some(status::LinkState::Pending))) {
+                    return None;
+                }
+                let unresolved = states.iter()
+                    .filter(|(_, state)| !matches!(state, Some(status::LinkState::Linked)))
+                    .count();
+                if unresolved > 0 && timeout.is_some_and(|timeout| self.started.elapsed() < timeout) {
+                    return None;
+                }
+                for (link, state) in states {
+                    match state {
+                        Some(status::LinkState::Linked) => println!("linked \"{}\" -> \"{}\"", link.src.0, link.dst.0),
+                        Some(state) => println!("unresolved \"{}\" -> \"{}\": {state}", link.src.0, link.dst.0),
+                        None => println!("unresolved \"{}\" -> \"{}\"", link.src.0, link.dst.0),
+                    }
+                }
+                if unresolved == 0 {
+                    Some(QuitReason::Done)
+                } else {
+                    Some(QuitReason::Failed(format!("{unresolved} of {} links unresolved", self.config_links.0.len())))
+                }
+            }
+            Mode::Unlink(links) => {
+                for link in not_linked {
+                    warn!(src = %link.src.0, dst = %link.dst.0, "Cannot unlink, no such link");
+                }
+                let failed = not_linked.len() + self.failed_unlinks.len();
+                if failed > 0 {
+                    Some(QuitReason::Failed(format!("{failed} of {} links could not be removed", links.0.len())))
+                } else if self.graph_links().iter().all(|link| !links.0.contains(link)) {
+                    Some(QuitReason::Done)
+                } else {
+                    None
+                }
+            }
+        }
+    }
+
+    // Gives up on a one-shot run that PipeWire doesn't finish
+    fn timed_out(&self) -> Option<QuitReason> {
+        let deadline = self.mode.deadline()?;
+        (self.started.elapsed() > deadline)
+            .then(|| QuitReason::Failed(format!("Not done after {}", humantime::format_duration(deadline))))
+    }
+
+    fn link_ids(
+        &self,
+        name_dir_input_port_id: &HashMap<PortName, PortObjectId<Input>>,
+        name_dir_output_port_id: &HashMap<PortName, PortObjectId<Output>>,
+        named_link: &config::NamedLink,
+    ) -> Vec<LinkObjectId> {
+        match (
+            name_dir_output_port_id.get(&named_link.src),
+            name_dir_input_port_id.get(&named_link.dst),
+        ) {
+            (Some(src_port_id), Some(dst_port_id)) => {
+                let key = (src_port_id.clone(), dst_port_id.clone());
+                self.links_by_id.iter()
+                    .filter(|(_, link_key)| **link_key == key)
+                    .map(|(link_id, _)| link_id.clone())
+                    .collect()
+            }
+            _ => vec![],
+        }
+    }
+
+    // Returns whether there was a link to destroy
+    fn plan_unlink(
+        &self,
+        name_dir_input_port_id: &HashMap<PortName, PortObjectId<Input>>,
+        name_dir_output_port_id: &HashMap<PortName, PortObjectId<Output>>,
+        named_link: &config::NamedLink,
+        plan: &mut plan::Plan,
+    ) -> bool {
+        let found = !self.link_ids(name_dir_input_port_id, name_dir_output_port_id, named_link).is_empty();
+        if found {
+            plan.destroy.push(named_link.clone());
+        }
+        found
+    }
+
+    // Sends the planned changes to PipeWire
+    fn apply(
+        &mut self,
+        name_dir_input_port_id: &HashMap<PortName, PortObjectId<Input>>,
+        name_dir_output_port_id: &HashMap<PortName, PortObjectId<Output>>,
+        tx: &Arc<Mutex<pw::channel::Sender<PWRequest>>>,
+        plan: &plan::Plan,
+    ) {
+        let tx = tx.lock().expect("Failed to lock tx");
+        for named_link in plan.destroy.iter() {
+            let reason = self.audit_reasons.remove(named_link).unwrap_or(match self.mode {
+                Mode::Unlink(_) => audit::Reason::Unlink,
+                Mode::Restore(_) => audit::Reason::Prune,
+                _ => audit::Reason::Reload,
+            });
+            for link_id in self.link_ids(name_dir_input_port_id, name_dir_output_port_id, named_link) {
+                info!(src = %named_link.src.0, dst = %named_link.dst.0, link_id = %link_id.0, "Unlinking");
+                self.audit(audit::Action::Destroy, reason, named_link, Some(&link_id));
+                self.audit_owned.remove(named_link);
+                self.pending_destroys.insert(link_id.clone(), named_link.clone());
+                // could be exiting already
+                let _ignore = tx.send(PWRequest::DestroyLink(link_id));
+            }
+        }
+        for named_link in plan.create.iter() {
+            let src_port_id = &name_dir_output_port_id[&named_link.src];
+            let dst_port_id = &name_dir_input_port_id[&named_link.dst];
+            let src_port = self
+                .ports
+                .get(&src_port_id.clone().unknown())
+                .expect("could not find port by id")
+                .clone();
+            let dst_port = self
+                .ports
+                .get(&dst_port_id.clone().unknown())
+                .expect("could not find port by id")
+                .clone();
+            info!(
+                src = %src_port.port_name.0, dst = %dst_port.port_name.0,
+                src_port_id = %src_port.port_id.0, src_node_id = %src_port.node_id.0,
+                dst_port_id = %dst_port.port_id.0, dst_node_id = %dst_port.node_id.0,
+                origin = %self.origin(named_link),
+                "Linking"
+            );
+            let linger = self.mode.linger() || self.on_exit(named_link) == config::OnExit::Linger;
+            // audited when it shows up in the graph; nothing else has happened to it since the start
+            let reason = self.audit_reasons.remove(named_link).unwrap_or(audit::Reason::Startup);
+            if self.audit_log.is_some() {
+                self.audit_pending.insert(named_link.clone(), (linger, reason));
+            }
+            // could be exiting already
+            let _ignore = tx.send(PWRequest::MakeLink(Box::new((src_port, dst_port)), linger));
+            self.failed_pairs.remove(&(named_link.src.clone(), named_link.dst.clone()));
+            self.set_link_state(named_link.clone(), status::LinkState::Pending);
+        }
     }
 }
 
-fn pw_loop(args: &Args, config: &config::Config) -> Result<QuitReason, error::Error> {
+fn pw_loop(main: Arc<Mutex<Main>>) -> Result<QuitReason, error::Error> {
     pw::init();
 
     let mainloop = pw::MainLoop::new().expect("Failed to create Pipewire Mainloop");
@@ -468,24 +1688,42 @@ fn pw_loop(args: &Args, config: &config::Config) -> Result<QuitReason, error::Er
 
     let (global_tx, global_rx) = channel::<Message>();
     let global_remove_tx = global_tx.clone();
+    let link_failed_tx = global_tx.clone();
+    let midi_tx = global_tx.clone();
+    let sigint_tx = global_tx.clone();
+    let sigterm_tx = global_tx.clone();
+    let destroyed_tx = global_tx.clone();
 
     let (pwcontrol_tx, pwcontrol_rx) = pw::channel::channel();
     let pwcontrol_tx = Arc::new(Mutex::new(pwcontrol_tx));
 
     let quit_reason = Arc::new(Mutex::new(None));
+    let pending_sync = Rc::new(RefCell::new(None));
+    let destroy_syncs = Rc::new(RefCell::new(Vec::new())); // the sync after each destroyed link
+
+    let registry = Rc::new(core.get_registry().expect("wtf"));
 
     let _receiver = pwcontrol_rx.attach(&mainloop, {
         let mainloop = mainloop.clone();
         let quit_reason = quit_reason.clone();
         let core = core.clone();
+        let registry = registry.clone();
+        let pending_sync = pending_sync.clone();
+        let destroy_syncs = destroy_syncs.clone();
         let linksies = Rc::new(RefCell::new(Vec::new()));
         move |request| match request {
             PWRequest::Quit(quit_reason_) => {
 		let mut quit_reason = quit_reason.lock().expect("Failed to lock quit reason?!");
+		let error = matches!(quit_reason_, QuitReason::Error(_));
 		*quit_reason = Some(quit_reason_);
-		mainloop.quit()
+		// let the server handle our requests before disconnecting, unless it is broken
+		match core.sync(0) {
+		    Ok(seq) if !error => *pending_sync.borrow_mut() = Some(seq),
+		    _ => mainloop.quit(),
+		}
 	    }
-            PWRequest::MakeLink((output, input)) => {
+            PWRequest::MakeLink(ports, linger) => {
+                let (output, input) = *ports;
                 let link = core
                     .create_object::<pw::link::Link, _>(
                         // The actual name for a link factory might be different for your system,
@@ -495,18 +1733,32 @@ fn pw_loop(args: &Args, config: &config::Config) -> Result<QuitReason, error::Er
                             "link.output.port" => format!("{}", output.port_id.0),
                             "link.output.node" => format!("{}", output.node_id.0),
                             "link.input.port" => format!("{}", input.port_id.0),
-                            "link.input.node" => format!("{}", input.node_id.0)
+                            "link.input.node" => format!("{}", input.node_id.0),
+                            "object.linger" => if linger { "true" } else { "false" }
                         },
-                    )
-                    .expect("Failed to create object");
+                    );
                 //println!("Link: {link:?}");
-                linksies.borrow_mut().push(link);
+                match link {
+                    Ok(link) => linksies.borrow_mut().push(link),
+                    Err(error) => {
+                        let link = config::NamedLink { src: output.port_name, dst: input.port_name, on_exit: None };
+                        let _ignore = link_failed_tx.send(Message::LinkFailed(link, error.to_string()));
+                    }
+                }
+            }
+            PWRequest::DestroyLink(link_id) => {
+                let id = link_id.0.parse().expect("Link id is numeric");
+                if let Err(error) = registry.destroy_global(id).into_sync_result() {
+                    error!(link_id = %link_id.0, %error, "Failed to destroy link");
+                }
+                // by its answer the server has removed the link, or failed to
+                if let Ok(seq) = core.sync(0) {
+                    destroy_syncs.borrow_mut().push((seq, link_id));
+                }
             }
         }
     });
 
-    let registry = core.get_registry().expect("wtf");
-
      let _listener = core
         .add_listener_local()
         .error({
@@ -516,21 +1768,65 @@ fn pw_loop(args: &Args, config: &config::Config) -> Result<QuitReason, error::Er
 		error_callback(a, b, c, msg, &tx)
 	    }
 	})
+        .done({
+	    let mainloop = mainloop.clone();
+	    move |id, seq| {
+		if id != pw::PW_ID_CORE {
+		    return;
+		}
+		if Some(seq) == *pending_sync.borrow() {
+		    mainloop.quit()
+		}
+		let mut destroy_syncs = destroy_syncs.borrow_mut();
+		if let Some(index) = destroy_syncs.iter().position(|(destroy_seq, _)| *destroy_seq == seq) {
+		    let (_, link_id) = destroy_syncs.swap_remove(index);
+		    let _ignore = destroyed_tx.send(Message::Destroyed(link_id));
+		}
+	    }
+	})
     //     .info(info_callback)
-    //     .done(done_callback)
         .register();
 
+    // exit through the control thread, so that it can clean up
+    let sigint = mainloop.add_signal_local(pw::Signal::SIGINT, move || {
+	let _ignore = sigint_tx.send(Message::Shutdown);
+    });
+    let sigterm = mainloop.add_signal_local(pw::Signal::SIGTERM, move || {
+	let _ignore = sigterm_tx.send(Message::Shutdown);
+    });
+
     let _registry_listener = registry
         .add_listener_local()
         .global(move |msg| global_callback(&global_tx, msg))
         .global_remove(move |msg| global_remove_callback(&global_remove_tx, msg))
         .register();
 
-    let mut main = Main::new(config.links.clone(), args.dump);
-    let _thread = thread::spawn(move || main.control_thread(global_rx, pwcontrol_tx));
+    let midi_input = {
+        let main = main.lock().expect("Failed to lock main");
+        match &main.config.midi {
+            // one-shot runs don't add nodes to the graph
+            Some(midi) if main.mode.is_daemon() => Some(midi::MidiInput::new(&mainloop, &midi.name, midi_tx)?),
+            _ => {
+                // or the control thread would never see the channel disconnect
+                drop(midi_tx);
+                None
+            }
+        }
+    };
+
+    main.lock().expect("Failed to lock main").reset_graph();
+    let thread = thread::spawn(move || Main::control_thread(main, global_rx, pwcontrol_tx));
 
     mainloop.run();
 
+    // let the control thread see the channel disconnect and finish
+    drop(_registry_listener);
+    drop(_receiver);
+    drop(midi_input);
+    drop(sigint);
+    drop(sigterm);
+    thread.join().expect("Control thread panicked");
+
     let quit_reason = quit_reason.lock().expect("Failed to lock quit reason?!");
 
     Ok(quit_reason.clone().expect("Quit reason not set, pwcontrol_rx.attach never called callback to exit?!"))
@@ -538,26 +1834,192 @@ fn pw_loop(args: &Args, config: &config::Config) -> Result<QuitReason, error::Er
 
 fn work() -> Result<(), error::Error> {
     let args = Args::parse();
+    logging::init(args.verbose, args.quiet, args.journald)?;
+
+    let control_socket = args.control_socket.clone().unwrap_or_else(control::default_socket_path);
+
+    if let Some(Command::Save) = args.command {
+	return match control::request(&control_socket, &control::Request::Save)? {
+	    control::Response::Ok => Ok(()),
+	    control::Response::Error(error) => Err(error::Error::ControlError(error)),
+	    response => Err(error::Error::ControlError(format!("Unexpected response {response:?}"))),
+	};
+    }
+
+    if let Some(Command::Status) = args.command {
+	return match control::request(&control_socket, &control::Request::Status)? {
+	    control::Response::Status(entries) => {
+		for entry in entries {
+		    println!("{entry}");
+		}
+		Ok(())
+	    }
+	    control::Response::Error(error) => Err(error::Error::ControlError(error)),
+	    response => Err(error::Error::ControlError(format!("Unexpected response {response:?}"))),
+	};
+    }
+
+    if let (Some(Command::Diff), None) = (&args.command, &args.config) {
+	Args::command().error(clap::error::ErrorKind::MissingRequiredArgument, "diff needs --config").exit();
+    }
 
-    let config = {
-	match args.config.clone().map(|config| config::Config::load(&config)) {
-	    Some(result) => result?,
-	    None => config::Config::default(),
+    if let Some(format @ (Format::Dot | Format::Qpwgraph | Format::PwLinkScript)) = args.format {
+	if !args.dump {
+	    let name = format.to_possible_value().expect("Formats have names").get_name().to_string();
+	    Args::command().error(clap::error::ErrorKind::ArgumentConflict, format!("--format {name} is only for --dump")).exit();
 	}
+    }
+    if args.format == Some(Format::Json) && args.dump && args.group_by_node {
+	Args::command().error(clap::error::ErrorKind::ArgumentConflict, "--group-by-node needs comments, which JSON doesn't have").exit();
+    }
+
+    let (config, mode) = match &args.command {
+	Some(Command::Import { file, from, resolve }) => {
+	    let import = import::read(file, *from)?;
+	    for skipped in import.skipped.iter() {
+		warn!("Skipping {skipped}, not a PipeWire link");
+	    }
+	    let format = match args.format {
+		Some(Format::Json) => config::ConfigFormat::Json,
+		_ => config::ConfigFormat::Yaml,
+	    };
+	    if !resolve {
+		let links = import.compressed().links;
+		print!("{}", config::Config { links, ..config::Config::default() }.dump(format));
+		return Ok(());
+	    }
+	    (config::Config::default(), Mode::Import(import, format))
+	}
+	Some(Command::Snapshot { command: SnapshotCommand::Save { file } }) => {
+	    (config::Config::default(), Mode::SnapshotSave(file.clone()))
+	}
+	Some(Command::Snapshot { command: SnapshotCommand::Restore { file, prune } }) => {
+	    let restore = snapshot::Restore {
+		snapshot: snapshot::Snapshot::load(file)?,
+		prune: *prune,
+		missing: 0,
+		pruned: vec![],
+	    };
+	    (config::Config::default(), Mode::Restore(restore))
+	}
+	Some(Command::Learn { config }) => {
+	    let config = match config::Config::load(config, args.config_format) {
+		Err(config::Error::IOError(error)) if error.kind() == std::io::ErrorKind::NotFound => config::Config::default(),
+		result => result?,
+	    };
+	    (config, Mode::Learn)
+	}
+	Some(Command::ListPorts { node, regex, direction }) => {
+	    let options = list::ListOptions {
+		nodes: node.clone(),
+		regex: regex.clone(),
+		direction: direction.clone(),
+		format: args.format.unwrap_or(Format::Yaml),
+	    };
+	    (config::Config::default(), Mode::ListPorts(options))
+	}
+	Some(Command::Link { src, dst, linger }) => {
+	    let links = config::expand_links(config::NamedLinks(vec![config::NamedLink::new(src, dst)]))?;
+	    (config::Config { links, ..config::Config::default() }, Mode::Link { linger: *linger })
+	}
+	Some(Command::Unlink { src, dst }) => {
+	    let links = config::expand_links(config::NamedLinks(vec![config::NamedLink::new(src, dst)]))?;
+	    (config::Config::default(), Mode::Unlink(links))
+	}
+	_ => {
+	    let config = match args.config.clone().map(|config| config::Config::load(&config, args.config_format)) {
+		Some(result) => result?,
+		None => config::Config::default(),
+	    };
+	    let mode = if let Some(Command::Diff) = args.command {
+		Mode::Diff
+	    } else if let Some(Command::Monitor) = args.command {
+		Mode::Monitor(args.format.unwrap_or_default())
+	    } else if args.dump {
+		Mode::Dump(dump::DumpOptions {
+		    compress: args.compress,
+		    group_by_node: args.group_by_node,
+		    filter: dump::Filter { nodes: args.node.clone(), ports: args.port.clone(), regex: args.regex.clone() },
+		    format: args.format.unwrap_or_default(),
+		})
+	    } else if args.dry_run {
+		Mode::DryRun(args.format.unwrap_or(Format::Yaml))
+	    } else if args.once {
+		Mode::Once { timeout: args.timeout.map(time::Duration::from_secs) }
+	    } else {
+		Mode::Daemon
+	    };
+	    (config, mode)
+	}
+    };
+    let daemon = mode.is_daemon();
+    let monitor = match mode {
+	Mode::Monitor(format) => Some(format),
+	_ => None,
+    };
+    let config_filename = match &args.command {
+	Some(Command::Learn { config }) => Some(config.clone()),
+	_ => args.config.clone(),
+    };
+
+    let mut main = Main::new(config, config_filename, args.config_format, mode);
+    if let Some(path) = &args.audit_log {
+	main.audit_log = Some(audit::AuditLog::open(path)?);
+    }
+    let main = Arc::new(Mutex::new(main));
+
+    // before any thread starts, or one of them would get the signals and die with them
+    signals::block()?;
+
+    if let Some(format) = monitor {
+	monitor::start(main.clone(), format);
+    }
+
+    let _control_server = if !daemon {
+	None
+    } else {
+	match control::Server::start(&control_socket, main.clone()) {
+	    Ok(server) => Some(server),
+	    Err(error) => {
+		warn!(%error, "Control socket disabled");
+		None
+	    }
+	}
+    };
+
+    let _dbus_connection = match &args.dbus {
+	Some(name) if daemon => Some(dbus::start(name, args.dbus_address.as_deref(), main.clone())?),
+	_ => None,
+    };
+
+    if let (Some(port), true) = (args.osc_port, daemon) {
+	osc::start(port, main.clone())?;
+    }
+
+    let _tui = if args.tui && daemon {
+	Some(tui::start(main.clone(), &tui::default_log_path())?)
+    } else {
+	None
     };
 
     loop {
-	match pw_loop(&args, &config) {
+	match pw_loop(main.clone()) {
 	    Ok(QuitReason::Done) => break Ok(()),
-	    Ok(QuitReason::Error) => (),
+	    // a one-shot run would repeat its output and changes
+	    Ok(QuitReason::Error(message)) =>
+		if !daemon {
+		    break Err(error::Error::ServerError(message))
+		},
+	    Ok(QuitReason::Failed(message)) => break Err(error::Error::LinkError(message)),
 	    Err(error @ error::Error::PipewireError(_)) =>
-		if args.dump {
+		if !daemon {
 		    break Err(error)
 		} else {
-                    eprintln!(
-                        "pw-connections: Pipewire error: {error}; restarting after 1 second"
-		    );
-		    thread::sleep(time::Duration::from_millis(1000))
+                    error!(%error, "PipeWire error; restarting after 1 second");
+		    // there is no main loop to shut down through
+		    if signals::wait(time::Duration::from_millis(1000)) {
+			break Ok(())
+		    }
 		}
 	    Err(error) =>
 		break Err(error)
@@ -570,6 +2032,93 @@ fn main() {
         Ok(()) => (),
         Err(error) => {
             eprintln!("pw-connections: {error}");
+            // 2 tells that PipeWire was fine but some links could not be made or removed
+            std::process::exit(if matches!(error, error::Error::LinkError(_)) { 2 } else { 1 });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    thread_local! {
+        static FIRED: RefCell<Vec<String>> = const { RefCell::new(Vec::new()) };
+    }
+
+    // Records the nodes the device hooks would be run for
+    fn record_hooks(hooks: &[config::Hook], trigger: &hooks::Trigger) {
+        if let hooks::Trigger::Device(node) = trigger {
+            if hooks.iter().any(|hook| hooks::matches(hook, trigger)) {
+                FIRED.with(|fired| fired.borrow_mut().push(node.name.clone()));
+            }
         }
     }
+
+    #[test]
+    fn device_hooks_after_sync() {
+        let yaml = "links: []\nhooks:\n  - on: device_appeared\n    command: 'true'\n";
+        let config = config::Config::deserialize(yaml, "hooks.yaml", config::ConfigFormat::Yaml).unwrap();
+        let mut main = Main::new(config, None, None, Mode::Daemon);
+        main.hook_runner = record_hooks;
+        let node = |id: &str, name: &str| Message::Object(Object {
+            id: ObjectId(id.to_string()),
+            properties: HashMap::from([("node.name".to_string(), name.to_string())]),
+        });
+        let fired = || FIRED.with(|fired| fired.borrow().clone());
+
+        main.process_message(node("40", "there.at.start"));
+        assert!(fired().is_empty());
+        main.graph_synced = true;
+        main.process_message(node("41", "plugged.in"));
+        assert_eq!(fired(), ["plugged.in"]);
+        // the graph of a new connection is not news either
+        main.reset_graph();
+        main.process_message(node("41", "plugged.in"));
+        assert_eq!(fired(), ["plugged.in"]);
+    }
+
+    #[test]
+    fn audit_reasons_of_ports() {
+        let yaml = "links:\n  - {src: a:out, dst: b:in}\n  - {src: a:out, dst: c:in}\n";
+        let config = config::Config::deserialize(yaml, "audit.yaml", config::ConfigFormat::Yaml).unwrap();
+        let mut main = Main::new(config, None, None, Mode::Daemon);
+        main.audit_log = Some(audit::AuditLog::open(std::path::Path::new("/dev/null")).unwrap());
+        let port = |id: &str, name: &str, direction: &str| Message::Object(Object {
+            id: ObjectId(id.to_string()),
+            properties: [("port.name", name), ("node.id", "1"), ("port.id", "0"), ("port.direction", direction)]
+                .iter().map(|(key, value)| (key.to_string(), value.to_string())).collect(),
+        });
+        main.process_message(port("10", "a:out", "out"));
+        main.process_message(port("11", "b:in", "in"));
+        main.graph_synced = true;
+        assert!(main.audit_reasons.is_empty());
+
+        main.process_message(Message::Remove(ObjectId("11".to_string())));
+        main.process_message(port("12", "b:in", "in"));
+        main.process_message(port("13", "c:in", "in"));
+        assert_eq!(main.audit_reasons[&config::NamedLink::new("a:out", "b:in")], audit::Reason::DeviceReappeared);
+        assert_eq!(main.audit_reasons[&config::NamedLink::new("a:out", "c:in")], audit::Reason::PortAppeared);
+    }
+
+    #[test]
+    fn unlink_fails_when_the_link_stays() {
+        let config = config::Config::deserialize("links: []\n", "unlink.yaml", config::ConfigFormat::Yaml).unwrap();
+        let links = config::NamedLinks(vec![config::NamedLink::new("a:out", "b:in")]);
+        let mut main = Main::new(config, None, None, Mode::Unlink(links));
+        let object = |id: &str, properties: &[(&str, &str)]| Message::Object(Object {
+            id: ObjectId(id.to_string()),
+            properties: properties.iter().map(|(key, value)| (key.to_string(), value.to_string())).collect(),
+        });
+        main.process_message(object("10", &[("port.name", "a:out"), ("node.id", "1"), ("port.id", "0"), ("port.direction", "out")]));
+        main.process_message(object("11", &[("port.name", "b:in"), ("node.id", "2"), ("port.id", "0"), ("port.direction", "in")]));
+        main.process_message(object("20", &[("link.output.port", "10"), ("link.output.node", "1"),
+                                            ("link.input.port", "11"), ("link.input.node", "2")]));
+        let link_id = LinkObjectId("20".to_string());
+        main.pending_destroys.insert(link_id.clone(), config::NamedLink::new("a:out", "b:in"));
+
+        assert!(main.finished(&plan::Plan::default(), &[]).is_none());
+        main.process_message(Message::Destroyed(link_id));
+        assert!(matches!(main.finished(&plan::Plan::default(), &[]), Some(QuitReason::Failed(_))));
+    }
 }
@@ -0,0 +1,244 @@
+use std::ffi::CString;
+use std::os::raw::c_void;
+use std::ptr;
+use std::sync::mpsc::Sender;
+
+use libspa_sys as spa_sys;
+use pipewire as pw;
+use pw::sys as pw_sys;
+
+use crate::config::MidiTrigger;
+use crate::Message;
+
+// from spa/utils/type.h and spa/control/control.h
+const SPA_TYPE_SEQUENCE: u32 = 16;
+const SPA_CONTROL_MIDI: u32 = 2;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MidiMessage {
+    ProgramChange { channel: u8, program: u8 },
+    ControlChange { channel: u8, controller: u8, value: u8 },
+}
+
+impl MidiMessage {
+    /// Parses the messages we react to; channels are numbered from 1
+    pub fn parse(bytes: &[u8]) -> Option<MidiMessage> {
+        match *bytes {
+            [status, program, ..] if status & 0xf0 == 0xc0 => Some(MidiMessage::ProgramChange {
+                channel: (status & 0x0f) + 1,
+                program,
+            }),
+            [status, controller, value, ..] if status & 0xf0 == 0xb0 => Some(MidiMessage::ControlChange {
+                channel: (status & 0x0f) + 1,
+                controller,
+                value,
+            }),
+            _ => None,
+        }
+    }
+}
+
+pub fn trigger_matches(trigger: &MidiTrigger, message: &MidiMessage) -> bool {
+    match *message {
+        MidiMessage::ProgramChange { channel, program } => {
+            trigger.channel.is_none_or(|ch| ch == channel) && trigger.program == Some(program)
+        }
+        MidiMessage::ControlChange {
+            channel,
+            controller,
+            value,
+        } => {
+            trigger.channel.is_none_or(|ch| ch == channel)
+                && trigger.cc == Some(controller)
+                && trigger.value.map_or(value >= 64, |expected| expected == value)
+        }
+    }
+}
+
+fn read_u32(data: &[u8], pos: usize) -> Option<u32> {
+    let bytes = data.get(pos..pos + 4)?;
+    Some(u32::from_ne_bytes(bytes.try_into().ok()?))
+}
+
+/// Returns the MIDI events of a spa_pod_sequence
+pub fn sequence_midi_events(data: &[u8]) -> Vec<&[u8]> {
+    let mut events = vec![];
+    let (Some(size), Some(SPA_TYPE_SEQUENCE)) = (read_u32(data, 0), read_u32(data, 4)) else {
+        return events;
+    };
+    let Some(body) = data.get(8..8 + size as usize) else {
+        return events;
+    };
+    // skip the unit and padding of spa_pod_sequence_body
+    let mut pos = 8;
+    // each spa_pod_control is offset, type and the value pod padded to 8 bytes
+    while let (Some(control_type), Some(value_size)) = (read_u32(body, pos + 4), read_u32(body, pos + 8)) {
+        let value_size = value_size as usize;
+        let Some(value) = body.get(pos + 16..pos + 16 + value_size) else {
+            break;
+        };
+        if control_type == SPA_CONTROL_MIDI {
+            events.push(value);
+        }
+        pos += 16 + ((value_size + 7) & !7);
+    }
+    events
+}
+
+struct State {
+    port: *mut c_void,
+    tx: Sender<Message>,
+}
+
+/// A PipeWire node with one MIDI input port, sending what it receives to the control thread
+pub struct MidiInput {
+    filter: *mut pw_sys::pw_filter,
+    _events: Box<pw_sys::pw_filter_events>,
+    state: Box<State>,
+}
+
+unsafe extern "C" fn on_process(data: *mut c_void, _position: *mut spa_sys::spa_io_position) {
+    let state = &*(data as *const State);
+    let buffer = pw_sys::pw_filter_dequeue_buffer(state.port);
+    if buffer.is_null() {
+        return;
+    }
+    let spa_buffer = (*buffer).buffer;
+    if (*spa_buffer).n_datas > 0 {
+        let data = &*(*spa_buffer).datas;
+        if !data.data.is_null() && !data.chunk.is_null() {
+            let offset = (*data.chunk).offset.min(data.maxsize) as usize;
+            let size = ((*data.chunk).size as usize).min(data.maxsize as usize - offset);
+            let bytes = std::slice::from_raw_parts((data.data as *const u8).add(offset), size);
+            for event in sequence_midi_events(bytes) {
+                if let Some(message) = MidiMessage::parse(event) {
+                    // could be exiting already
+                    let _ignore = state.tx.send(Message::Midi(message));
+                }
+            }
+        }
+    }
+    pw_sys::pw_filter_queue_buffer(state.port, buffer);
+}
+
+impl MidiInput {
+    pub fn new(loop_: &pw::LoopRef, name: &str, tx: Sender<Message>) -> Result<MidiInput, pw::Error> {
+        // SAFETY: pw_filter_events is a plain C struct of a version and optional callbacks
+        let mut events: Box<pw_sys::pw_filter_events> = Box::new(unsafe { std::mem::zeroed() });
+        events.version = pw_sys::PW_VERSION_FILTER_EVENTS;
+        events.process = Some(on_process);
+        let mut state = Box::new(State {
+            port: ptr::null_mut(),
+            tx,
+        });
+
+        let c_name = CString::new(name).expect("Null byte in MIDI input name");
+        let properties = pw::properties! {
+            "media.type" => "Midi",
+            "media.category" => "Filter",
+            "media.role" => "DSP",
+            "node.name" => name
+        };
+        let filter = unsafe {
+            pw_sys::pw_filter_new_simple(
+                loop_.as_ptr(),
+                c_name.as_ptr(),
+                properties.into_raw(),
+                &*events,
+                &mut *state as *mut State as *mut c_void,
+            )
+        };
+        if filter.is_null() {
+            return Err(pw::Error::CreationFailed);
+        }
+        let mut midi_input = MidiInput {
+            filter,
+            _events: events,
+            state,
+        };
+
+        let port_properties = pw::properties! {
+            "format.dsp" => "8 bit raw midi",
+            "port.name" => "input",
+            "port.alias" => format!("{name}:input")
+        };
+        midi_input.state.port = unsafe {
+            pw_sys::pw_filter_add_port(
+                filter,
+                spa_sys::SPA_DIRECTION_INPUT,
+                pw_sys::pw_filter_port_flags_PW_FILTER_PORT_FLAG_MAP_BUFFERS,
+                std::mem::size_of::<usize>(),
+                port_properties.into_raw(),
+                ptr::null_mut(),
+                0,
+            )
+        };
+        if midi_input.state.port.is_null() {
+            return Err(pw::Error::CreationFailed);
+        }
+        let result = unsafe {
+            pw_sys::pw_filter_connect(filter, pw_sys::pw_filter_flags_PW_FILTER_FLAG_NONE, ptr::null_mut(), 0)
+        };
+        if result < 0 {
+            return Err(pw::Error::CreationFailed);
+        }
+        Ok(midi_input)
+    }
+}
+
+impl Drop for MidiInput {
+    fn drop(&mut self) {
+        unsafe { pw_sys::pw_filter_destroy(self.filter) }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn control(data: &mut Vec<u8>, control_type: u32, value: &[u8]) {
+        data.extend_from_slice(&0u32.to_ne_bytes()); // offset
+        data.extend_from_slice(&control_type.to_ne_bytes());
+        data.extend_from_slice(&(value.len() as u32).to_ne_bytes());
+        data.extend_from_slice(&9u32.to_ne_bytes()); // SPA_TYPE_Bytes
+        data.extend_from_slice(value);
+        data.resize((data.len() + 7) & !7, 0);
+    }
+
+    #[test]
+    fn sequence() {
+        let mut body = vec![0; 8];
+        control(&mut body, SPA_CONTROL_MIDI, &[0xc2, 5]);
+        control(&mut body, 1, &[1, 2, 3, 4, 5, 6, 7, 8, 9]);
+        control(&mut body, SPA_CONTROL_MIDI, &[0xb0, 64, 127]);
+        let mut data = vec![];
+        data.extend_from_slice(&(body.len() as u32).to_ne_bytes());
+        data.extend_from_slice(&SPA_TYPE_SEQUENCE.to_ne_bytes());
+        data.extend_from_slice(&body);
+
+        let events = sequence_midi_events(&data);
+        assert_eq!(events, vec![&[0xc2, 5][..], &[0xb0, 64, 127][..]]);
+        assert_eq!(
+            events.iter().map(|event| MidiMessage::parse(event)).collect::<Vec<_>>(),
+            vec![
+                Some(MidiMessage::ProgramChange { channel: 3, program: 5 }),
+                Some(MidiMessage::ControlChange { channel: 1, controller: 64, value: 127 }),
+            ]
+        );
+        assert!(sequence_midi_events(&data[..20]).is_empty());
+    }
+
+    #[test]
+    fn triggers() {
+        let trigger: MidiTrigger = serde_yaml::from_str("{cc: 64, channel: 1, profile: a}").unwrap();
+        let cc = |channel, value| MidiMessage::ControlChange { channel, controller: 64, value };
+        assert!(trigger_matches(&trigger, &cc(1, 127)));
+        assert!(!trigger_matches(&trigger, &cc(1, 0)));
+        assert!(!trigger_matches(&trigger, &cc(2, 127)));
+        assert!(!trigger_matches(&trigger, &MidiMessage::ProgramChange { channel: 1, program: 64 }));
+
+        let trigger: MidiTrigger = serde_yaml::from_str("{program: 3, profile: a}").unwrap();
+        assert!(trigger_matches(&trigger, &MidiMessage::ProgramChange { channel: 16, program: 3 }));
+        assert!(!trigger_matches(&trigger, &MidiMessage::ProgramChange { channel: 16, program: 4 }));
+    }
+}
@@ -0,0 +1,95 @@
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use serde_derive::Serialize;
+
+use crate::config::{NamedLinks, PortName};
+use crate::events::Event;
+use crate::{Format, Main};
+
+/// An event as printed as a JSON line
+#[derive(Debug, Serialize)]
+struct Record<'a> {
+    event: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    port: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    src: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    dst: Option<&'a str>,
+    configured: bool,
+}
+
+/// Whether the event touches a configured link: the link itself, or a port at either end of one
+pub fn configured(event: &Event, links: &NamedLinks) -> bool {
+    let is_end = |port: &PortName| links.0.iter().any(|link| link.src == *port || link.dst == *port);
+    match event {
+        Event::PortAppeared { port } | Event::PortRemoved { port } => is_end(port),
+        Event::LinkCreated { src, dst } | Event::LinkLost { src, dst } => {
+            links.0.iter().any(|link| link.src == *src && link.dst == *dst)
+        }
+    }
+}
+
+pub fn line(event: &Event, configured: bool, format: Format) -> String {
+    let (name, port, link) = match event {
+        Event::PortAppeared { port } => ("port_appeared", Some(port), None),
+        Event::PortRemoved { port } => ("port_removed", Some(port), None),
+        Event::LinkCreated { src, dst } => ("link_created", None, Some((src, dst))),
+        Event::LinkLost { src, dst } => ("link_lost", None, Some((src, dst))),
+    };
+    if format == Format::Json {
+        let record = Record {
+            event: name,
+            port: port.map(|port| port.0.as_str()),
+            src: link.map(|(src, _)| src.0.as_str()),
+            dst: link.map(|(_, dst)| dst.0.as_str()),
+            configured,
+        };
+        return serde_json::to_string(&record).expect("Failed to serialize json");
+    }
+    let subject = match (port, link) {
+        (Some(port), _) => format!("\"{}\"", port.0),
+        (_, Some((src, dst))) => format!("\"{}\" -> \"{}\"", src.0, dst.0),
+        (None, None) => unreachable!("Events have a port or a link"),
+    };
+    let mark = if configured { " (configured)" } else { "" };
+    format!("{} {subject}{mark}", name.replace('_', " "))
+}
+
+/// Prints the events of the graph as they happen
+pub fn start(main: Arc<Mutex<Main>>, format: Format) {
+    let events = main.lock().expect("Failed to lock main").subscribers.subscribe();
+    thread::spawn(move || {
+        for event in events {
+            let configured = configured(&event, &main.lock().expect("Failed to lock main").config_links);
+            println!("{}", line(&event, configured, format));
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::NamedLink;
+
+    #[test]
+    fn lines() {
+        let name = |name: &str| PortName(name.to_string());
+        let links = NamedLinks(vec![NamedLink::new("synth:out", "speaker:in")]);
+        let created = Event::LinkCreated { src: name("synth:out"), dst: name("speaker:in") };
+        let removed = Event::PortRemoved { port: name("recorder:in") };
+        assert!(configured(&created, &links));
+        assert!(configured(&Event::PortAppeared { port: name("speaker:in") }, &links));
+        assert!(!configured(&removed, &links));
+        assert!(!configured(&Event::LinkLost { src: name("synth:out"), dst: name("recorder:in") }, &links));
+
+        assert_eq!(line(&created, true, Format::Yaml), "link created \"synth:out\" -> \"speaker:in\" (configured)");
+        assert_eq!(line(&removed, false, Format::Yaml), "port removed \"recorder:in\"");
+        assert_eq!(
+            line(&created, true, Format::Json),
+            r#"{"event":"link_created","src":"synth:out","dst":"speaker:in","configured":true}"#
+        );
+        assert_eq!(line(&removed, false, Format::Json), r#"{"event":"port_removed","port":"recorder:in","configured":false}"#);
+    }
+}
@@ -0,0 +1,311 @@
+use std::collections::VecDeque;
+use std::io;
+use std::net::{SocketAddr, UdpSocket};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use tracing::{debug, warn};
+
+use crate::config::NamedLink;
+use crate::control::{Request, Response};
+use crate::events::Event;
+use crate::Main;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Arg {
+    Int(i32),
+    Float(f32),
+    String(String),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Packet {
+    pub address: String,
+    pub args: Vec<Arg>,
+}
+
+fn read_string(data: &[u8], pos: &mut usize) -> Option<String> {
+    let len = data.get(*pos..)?.iter().position(|&ch| ch == 0)?;
+    let str = String::from_utf8(data[*pos..*pos + len].to_vec()).ok()?;
+    // strings are zero terminated and padded to four bytes
+    *pos += (len + 4) & !3;
+    Some(str)
+}
+
+fn read_u32(data: &[u8], pos: &mut usize) -> Option<u32> {
+    let bytes = data.get(*pos..*pos + 4)?;
+    *pos += 4;
+    Some(u32::from_be_bytes(bytes.try_into().ok()?))
+}
+
+fn write_string(data: &mut Vec<u8>, str: &str) {
+    data.extend_from_slice(str.as_bytes());
+    data.resize((data.len() + 4) & !3, 0);
+}
+
+impl Packet {
+    pub fn new(address: &str, args: Vec<Arg>) -> Self {
+        Packet {
+            address: address.to_string(),
+            args,
+        }
+    }
+
+    /// Decodes an OSC message; bundles are not supported
+    pub fn decode(data: &[u8]) -> Option<Packet> {
+        let mut pos = 0;
+        let address = read_string(data, &mut pos)?;
+        if !address.starts_with('/') {
+            return None;
+        }
+        let mut args = vec![];
+        if pos < data.len() {
+            let tags = read_string(data, &mut pos)?;
+            for tag in tags.strip_prefix(',')?.chars() {
+                args.push(match tag {
+                    'i' => Arg::Int(read_u32(data, &mut pos)? as i32),
+                    'f' => Arg::Float(f32::from_bits(read_u32(data, &mut pos)?)),
+                    's' => Arg::String(read_string(data, &mut pos)?),
+                    _ => return None,
+                });
+            }
+        }
+        Some(Packet { address, args })
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        let mut data = vec![];
+        write_string(&mut data, &self.address);
+        let tags: String = self
+            .args
+            .iter()
+            .map(|arg| match arg {
+                Arg::Int(_) => 'i',
+                Arg::Float(_) => 'f',
+                Arg::String(_) => 's',
+            })
+            .collect();
+        write_string(&mut data, &format!(",{tags}"));
+        for arg in &self.args {
+            match arg {
+                Arg::Int(value) => data.extend_from_slice(&value.to_be_bytes()),
+                Arg::Float(value) => data.extend_from_slice(&value.to_bits().to_be_bytes()),
+                Arg::String(value) => write_string(&mut data, value),
+            }
+        }
+        data
+    }
+
+    fn strings(&self) -> Option<Vec<&str>> {
+        self.args
+            .iter()
+            .map(|arg| match arg {
+                Arg::String(str) => Some(str.as_str()),
+                _ => None,
+            })
+            .collect()
+    }
+}
+
+fn named_link(args: &[&str]) -> Option<NamedLink> {
+    match args {
+        [src, dst] => Some(NamedLink::new(src, dst)),
+        _ => None,
+    }
+}
+
+// Maps an OSC message to the corresponding link engine request
+fn request(packet: &Packet) -> Result<Request, String> {
+    let args = packet
+        .strings()
+        .ok_or_else(|| "Arguments must be strings".to_string())?;
+    let bad_args = || format!("Bad arguments for {}", packet.address);
+    match packet.address.as_str() {
+        "/pwc/profile" => match args[..] {
+            [] | [""] => Ok(Request::SetProfile(None)),
+            [profile] => Ok(Request::SetProfile(Some(profile.to_string()))),
+            _ => Err(bad_args()),
+        },
+        "/pwc/link" => named_link(&args).map(Request::AddLink).ok_or_else(bad_args),
+        "/pwc/unlink" => named_link(&args).map(Request::RemoveLink).ok_or_else(bad_args),
+        "/pwc/reload" => Ok(Request::Reload),
+        "/pwc/save" => Ok(Request::Save),
+        "/pwc/status" => Ok(Request::Status),
+        address => Err(format!("Unknown address {address}")),
+    }
+}
+
+fn string(str: &str) -> Arg {
+    Arg::String(str.to_string())
+}
+
+// Performs the request and returns the feedback for the sender
+fn handle_packet(packet: &Packet, main: &Mutex<Main>) -> Vec<Packet> {
+    let request = match request(packet) {
+        Ok(request) => request,
+        Err(error) => return vec![Packet::new("/pwc/error", vec![string(&packet.address), string(&error)])],
+    };
+    let mut main = main.lock().expect("Failed to lock main");
+    let mut replies = match main.handle_request(request) {
+        Response::Ok => vec![Packet::new("/pwc/ok", vec![string(&packet.address)])],
+        Response::Status(entries) => entries
+            .iter()
+            .map(|entry| {
+                Packet::new(
+                    "/pwc/status",
+                    vec![
+                        string(&entry.link.src.0),
+                        string(&entry.link.dst.0),
+                        string(&entry.state.to_string()),
+                        Arg::Int(entry.relinks as i32),
+                    ],
+                )
+            })
+            .collect(),
+        Response::Error(error) => vec![Packet::new("/pwc/error", vec![string(&packet.address), string(&error)])],
+    };
+    replies.push(Packet::new(
+        "/pwc/profile",
+        vec![string(main.profile.as_deref().unwrap_or(""))],
+    ));
+    replies
+}
+
+fn event_packet(event: &Event) -> Packet {
+    match event {
+        Event::PortAppeared { port } => Packet::new("/pwc/port", vec![string(&port.0)]),
+        Event::PortRemoved { port } => Packet::new("/pwc/unport", vec![string(&port.0)]),
+        Event::LinkCreated { src, dst } => Packet::new("/pwc/linked", vec![string(&src.0), string(&dst.0)]),
+        Event::LinkLost { src, dst } => Packet::new("/pwc/unlinked", vec![string(&src.0), string(&dst.0)]),
+    }
+}
+
+// How many senders get the events; those heard from longest ago make room for new ones
+const MAX_CLIENTS: usize = 16;
+
+/// The senders of valid messages, most recently heard from last
+#[derive(Debug, Default)]
+struct Clients(VecDeque<SocketAddr>);
+
+impl Clients {
+    fn heard_from(&mut self, client: SocketAddr) {
+        self.0.retain(|known| *known != client);
+        self.0.push_back(client);
+        if self.0.len() > MAX_CLIENTS {
+            self.0.pop_front();
+        }
+    }
+}
+
+/// Listens to OSC messages on the localhost UDP port. The last senders of valid messages
+/// receive the following link events as well, until sending to them fails.
+pub fn start(port: u16, main: Arc<Mutex<Main>>) -> io::Result<()> {
+    let socket = UdpSocket::bind(("127.0.0.1", port))?;
+    let event_socket = socket.try_clone()?;
+    let clients = Arc::new(Mutex::new(Clients::default()));
+
+    let events = main.lock().expect("Failed to lock main").subscribers.subscribe();
+    thread::spawn({
+        let clients = clients.clone();
+        move || {
+            for event in events {
+                let data = event_packet(&event).encode();
+                let mut clients = clients.lock().expect("Failed to lock clients");
+                clients.0.retain(|client| match event_socket.send_to(&data, client) {
+                    Ok(_) => true,
+                    Err(error) => {
+                        debug!(%client, %error, "Dropping OSC client");
+                        false
+                    }
+                });
+            }
+        }
+    });
+
+    thread::spawn(move || {
+        let mut buffer = [0u8; 65536];
+        loop {
+            let (len, sender) = match socket.recv_from(&mut buffer) {
+                Ok(received) => received,
+                Err(error) => {
+                    warn!(%error, "OSC receive error");
+                    continue;
+                }
+            };
+            let replies = match Packet::decode(&buffer[..len]) {
+                Some(packet) => {
+                    clients.lock().expect("Failed to lock clients").heard_from(sender);
+                    handle_packet(&packet, &main)
+                }
+                None => vec![Packet::new("/pwc/error", vec![string(""), string("Cannot decode OSC message")])],
+            };
+            for reply in replies {
+                if let Err(error) = socket.send_to(&reply.encode(), sender) {
+                    warn!(%sender, %error, "OSC send error");
+                    clients.lock().expect("Failed to lock clients").0.retain(|client| *client != sender);
+                    break;
+                }
+            }
+        }
+    });
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn codec() {
+        let packet = Packet::new(
+            "/pwc/link",
+            vec![string("a"), string("bcde"), Arg::Int(-2), Arg::Float(0.5)],
+        );
+        let data = packet.encode();
+        assert_eq!(
+            data,
+            b"/pwc/link\0\0\0,ssif\0\0\0a\0\0\0bcde\0\0\0\0\xff\xff\xff\xfe\x3f\x00\x00\x00".to_vec()
+        );
+        assert_eq!(Packet::decode(&data), Some(packet));
+        assert_eq!(
+            Packet::decode(b"/pwc/reload\0"),
+            Some(Packet::new("/pwc/reload", vec![]))
+        );
+        assert_eq!(Packet::decode(b"/pwc/link\0\0\0,s\0\0a"), None);
+        assert_eq!(Packet::decode(b"pwc\0"), None);
+    }
+
+    #[test]
+    fn clients() {
+        let address = |port| SocketAddr::from(([127, 0, 0, 1], port));
+        let mut clients = Clients::default();
+        for port in 0..MAX_CLIENTS as u16 {
+            clients.heard_from(address(port));
+        }
+        clients.heard_from(address(0));
+        clients.heard_from(address(100));
+        assert_eq!(clients.0.len(), MAX_CLIENTS);
+        assert!(!clients.0.contains(&address(1)));
+        assert_eq!(clients.0.back(), Some(&address(100)));
+        assert!(clients.0.contains(&address(0)));
+    }
+
+    #[test]
+    fn requests() {
+        assert!(matches!(
+            request(&Packet::new("/pwc/profile", vec![string("gig")])),
+            Ok(Request::SetProfile(Some(profile))) if profile == "gig"
+        ));
+        assert!(matches!(
+            request(&Packet::new("/pwc/profile", vec![])),
+            Ok(Request::SetProfile(None))
+        ));
+        assert!(matches!(
+            request(&Packet::new("/pwc/unlink", vec![string("a"), string("b")])),
+            Ok(Request::RemoveLink(_))
+        ));
+        assert!(request(&Packet::new("/pwc/link", vec![string("a")])).is_err());
+        assert!(request(&Packet::new("/pwc/link", vec![string("a"), Arg::Int(1)])).is_err());
+        assert!(request(&Packet::new("/pwc/foo", vec![])).is_err());
+    }
+}
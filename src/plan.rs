@@ -0,0 +1,69 @@
+use serde_derive::Serialize;
+use std::fmt;
+
+use crate::config::NamedLink;
+use crate::status::LinkState;
+
+/// A configured link that cannot be made at the moment
+#[derive(Debug, Clone, Serialize)]
+pub struct Unresolved {
+    #[serde(flatten)]
+    pub link: NamedLink,
+    pub state: LinkState,
+}
+
+/// Changes to the graph decided by one round of reconciliation
+#[derive(Debug, Default, Serialize)]
+pub struct Plan {
+    pub create: Vec<NamedLink>,
+    pub destroy: Vec<NamedLink>,
+    pub unresolved: Vec<Unresolved>,
+}
+
+impl fmt::Display for Plan {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.create.is_empty() && self.destroy.is_empty() && self.unresolved.is_empty() {
+            return writeln!(f, "Nothing to do");
+        }
+        for link in &self.destroy {
+            writeln!(f, "- \"{}\" -> \"{}\"", link.src.0, link.dst.0)?;
+        }
+        for link in &self.create {
+            writeln!(f, "+ \"{}\" -> \"{}\"", link.src.0, link.dst.0)?;
+        }
+        for unresolved in &self.unresolved {
+            writeln!(
+                f,
+                "? \"{}\" -> \"{}\": {}",
+                unresolved.link.src.0, unresolved.link.dst.0, unresolved.state
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn output() {
+        assert_eq!(Plan::default().to_string(), "Nothing to do\n");
+        let plan = Plan {
+            create: vec![NamedLink::new("a:out", "b:in")],
+            destroy: vec![NamedLink::new("c:out", "d:in")],
+            unresolved: vec![Unresolved {
+                link: NamedLink::new("e:out", "f:in"),
+                state: LinkState::WaitingForDst,
+            }],
+        };
+        assert_eq!(
+            plan.to_string(),
+            "- \"c:out\" -> \"d:in\"\n+ \"a:out\" -> \"b:in\"\n? \"e:out\" -> \"f:in\": waiting for dst\n"
+        );
+        assert_eq!(
+            serde_json::to_string(&plan).unwrap(),
+            r#"{"create":[{"src":"a:out","dst":"b:in"}],"destroy":[{"src":"c:out","dst":"d:in"}],"unresolved":[{"src":"e:out","dst":"f:in","state":"waiting-for-dst"}]}"#
+        );
+    }
+}
@@ -0,0 +1,184 @@
+use crate::config::{self, Config, ConfigFormat, NamedLink, NamedLinks};
+
+/// What to change in the common links of a config file
+#[derive(Debug, Default)]
+pub struct Changes {
+    pub add: Vec<NamedLink>,
+    pub remove: Vec<NamedLink>,
+}
+
+/// What becomes of a links entry of the file
+#[derive(Debug, PartialEq)]
+enum Edit {
+    Keep,
+    Delete,
+    Replace(NamedLinks),
+}
+
+fn edits(entries: &[NamedLink], changes: &Changes) -> Result<Vec<Edit>, config::Error> {
+    let mut edits = vec![];
+    for entry in entries {
+        let links = config::expand_links(NamedLinks(vec![entry.clone()]))?;
+        let remaining: Vec<_> = links.0.iter().filter(|link| !changes.remove.contains(link)).cloned().collect();
+        edits.push(if remaining.len() == links.0.len() {
+            Edit::Keep
+        } else if remaining.is_empty() {
+            Edit::Delete
+        } else {
+            Edit::Replace(config::compress_links(&NamedLinks(remaining)))
+        });
+    }
+    Ok(edits)
+}
+
+/// The lines of each item of the top level links list of a YAML file, if it is a block list
+fn yaml_items(lines: &[&str]) -> Option<Vec<(usize, usize)>> {
+    let start = lines.iter().position(|line| line.trim_end() == "links:")? + 1;
+    let indentation = |line: &str| line.len() - line.trim_start().len();
+    let skipped = |line: &str| line.trim().is_empty() || line.trim_start().starts_with('#');
+    let first = lines[start..].iter().find(|line| !skipped(line))?;
+    let indent = indentation(first);
+    if !first.trim_start().starts_with("- ") {
+        return None;
+    }
+
+    let mut items: Vec<(usize, usize)> = vec![];
+    for (index, line) in lines.iter().enumerate().skip(start) {
+        if skipped(line) {
+            continue;
+        }
+        let line_indent = indentation(line);
+        if line_indent == indent && line.trim_start().starts_with("- ") {
+            items.push((index, index + 1));
+        } else if line_indent > indent && items.last().is_some_and(|item| item.1 == index) {
+            items.last_mut().expect("Checked above").1 += 1;
+        } else {
+            break;
+        }
+    }
+    Some(items)
+}
+
+fn yaml_list(links: &NamedLinks, indent: usize) -> Vec<String> {
+    serde_yaml::to_string(links)
+        .expect("Failed to serialize yaml")
+        .lines()
+        .map(|line| format!("{:indent$}{line}", ""))
+        .collect()
+}
+
+// Edits the lines of the file, leaving everything else as it is
+fn edit_yaml(contents: &str, entries: &[NamedLink], edits: &[Edit], add: &NamedLinks) -> Option<String> {
+    let lines: Vec<&str> = contents.lines().collect();
+    let items = yaml_items(&lines)?;
+    if items.len() != entries.len() {
+        return None;
+    }
+    // make sure each item is the entry we think it is
+    for ((start, end), entry) in items.iter().zip(entries) {
+        let parsed: Vec<NamedLink> = serde_yaml::from_str(&lines[*start..*end].join("\n")).ok()?;
+        if !matches!(parsed.as_slice(), [item] if item.src == entry.src && item.dst == entry.dst) {
+            return None;
+        }
+    }
+
+    let indent = lines[items[0].0].len() - lines[items[0].0].trim_start().len();
+    let mut output: Vec<String> = vec![];
+    let mut next = 0;
+    for ((start, end), edit) in items.iter().zip(edits) {
+        output.extend(lines[next..*start].iter().map(|line| line.to_string()));
+        match edit {
+            Edit::Keep => output.extend(lines[*start..*end].iter().map(|line| line.to_string())),
+            Edit::Delete => (),
+            Edit::Replace(links) => output.extend(yaml_list(links, indent)),
+        }
+        next = *end;
+    }
+    if !add.0.is_empty() {
+        output.extend(yaml_list(add, indent));
+    }
+    output.extend(lines[next..].iter().map(|line| line.to_string()));
+    Some(output.join("\n") + "\n")
+}
+
+/// Applies the changes to the contents of a config file: entries whose links are all removed are
+/// deleted, those partly removed are rewritten and the added links are appended, folded into
+/// braces. The other entries are left as they are, and so are the comments of a YAML file if its
+/// links are a block list; otherwise the file is serialized again.
+pub fn apply(contents: &str, filename: &str, format: ConfigFormat, changes: &Changes) -> Result<String, config::Error> {
+    let mut config = Config::deserialize(contents, filename, format)?;
+    let edits = edits(&config.links.0, changes)?;
+    let add = config::compress_links(&NamedLinks(changes.add.clone()));
+    if format == ConfigFormat::Yaml {
+        if let Some(contents) = edit_yaml(contents, &config.links.0, &edits, &add) {
+            return Ok(contents);
+        }
+    }
+
+    let mut links = NamedLinks::default();
+    for (entry, edit) in config.links.0.iter().zip(edits) {
+        match edit {
+            Edit::Keep => links.0.push(entry.clone()),
+            Edit::Delete => (),
+            Edit::Replace(replacement) => links.0.extend(replacement.0),
+        }
+    }
+    links.0.extend(add.0);
+    config.links = links;
+    Ok(config.dump(format))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn merge() {
+        let yaml = "# studio wiring
+links:
+  # the mic
+  - src: ALC257 Analog:capture_{FL,FR}
+    dst: Firefox:input_{FL,FR}
+  - {src: synth:out_1, dst: ALC257 Analog:playback_FL}  # left only
+  - src: a:out
+    dst: b:in
+
+profiles:
+  gig:
+    links: []
+";
+        let changes = Changes {
+            add: vec![NamedLink::new("x:out_1", "y:in_1"), NamedLink::new("x:out_2", "y:in_2")],
+            remove: vec![NamedLink::new("ALC257 Analog:capture_FR", "Firefox:input_FR"), NamedLink::new("a:out", "b:in")],
+        };
+        assert_eq!(
+            apply(yaml, "studio.yaml", ConfigFormat::Yaml, &changes).unwrap(),
+            "# studio wiring
+links:
+  # the mic
+  - src: ALC257 Analog:capture_FL
+    dst: Firefox:input_FL
+  - {src: synth:out_1, dst: ALC257 Analog:playback_FL}  # left only
+  - src: x:out_{1..2}
+    dst: y:in_{1..2}
+
+profiles:
+  gig:
+    links: []
+"
+        );
+
+        let json = r#"{"links": [{"src": "a:out_{1..3}", "dst": "b:in_{1..3}"}]}"#;
+        let changes = Changes {
+            add: vec![],
+            remove: vec![NamedLink::new("a:out_2", "b:in_2")],
+        };
+        let saved = apply(json, "studio.json", ConfigFormat::Json, &changes).unwrap();
+        let config = Config::deserialize(&saved, "studio.json", ConfigFormat::Json).unwrap();
+        assert_eq!(config.links.0, vec![NamedLink::new("a:out_{1,3}", "b:in_{1,3}")]);
+
+        // flow lists can't be edited in place
+        let saved = apply("links: [{src: a:out, dst: b:in}]\n", "x", ConfigFormat::Yaml, &changes).unwrap();
+        assert_eq!(saved, "links:\n- src: a:out\n  dst: b:in\n\n");
+    }
+}
@@ -0,0 +1,84 @@
+use std::io;
+use std::mem::MaybeUninit;
+use std::ptr;
+use std::time::Duration;
+
+// The signals that shut us down. PipeWire reads them through a signalfd, which only
+// sees them while they are blocked in every thread, not only in the one running the loop
+fn exit_signals() -> libc::sigset_t {
+    unsafe {
+        let mut set = MaybeUninit::uninit();
+        libc::sigemptyset(set.as_mut_ptr());
+        libc::sigaddset(set.as_mut_ptr(), libc::SIGINT);
+        libc::sigaddset(set.as_mut_ptr(), libc::SIGTERM);
+        set.assume_init()
+    }
+}
+
+/// Blocks SIGINT and SIGTERM in the calling thread and in the threads it starts afterwards
+pub fn block() -> io::Result<()> {
+    match unsafe { libc::pthread_sigmask(libc::SIG_BLOCK, &exit_signals(), ptr::null_mut()) } {
+        0 => Ok(()),
+        error => Err(io::Error::from_raw_os_error(error)),
+    }
+}
+
+/// Waits for a blocked SIGINT or SIGTERM for at most `timeout`; true if one came
+pub fn wait(timeout: Duration) -> bool {
+    let timeout = libc::timespec {
+        tv_sec: timeout.as_secs() as libc::time_t,
+        tv_nsec: timeout.subsec_nanos() as libc::c_long,
+    };
+    unsafe { libc::sigtimedwait(&exit_signals(), ptr::null_mut(), &timeout) > 0 }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::net::UnixStream;
+    use std::sync::{Arc, Mutex};
+
+    use crate::config::{Config, ConfigFormat};
+    use crate::control::{self, Request, Response};
+    use crate::{Main, Mode};
+
+    // In a child process, so that the test harness threads, which don't block it, can't get it
+    fn sigterm_with_control_server() -> bool {
+        let config = Config::deserialize("links: []\n", "signals.yaml", ConfigFormat::Yaml).unwrap();
+        let main = Arc::new(Mutex::new(Main::new(config, None, None, Mode::Daemon)));
+        let path = std::env::temp_dir().join(format!("pw-connections-signals-{}.sock", std::process::id()));
+        block().unwrap();
+        let _server = control::Server::start(&path, main).unwrap();
+        // a client that the server waits for while the signal comes
+        let _client = UnixStream::connect(&path).unwrap();
+        let status = |path| matches!(control::request(path, &Request::Status), Ok(Response::Status(_)));
+        if !status(&path) {
+            return false;
+        }
+        unsafe { libc::kill(libc::getpid(), libc::SIGTERM) };
+        wait(Duration::from_secs(5)) && status(&path)
+    }
+
+    #[test]
+    fn sigterm_waits_for_the_main_thread() {
+        match unsafe { libc::fork() } {
+            0 => {
+                // a panic must not unwind into the harness, which isn't there any more
+                let ok = std::panic::catch_unwind(sigterm_with_control_server).unwrap_or(false);
+                unsafe { libc::_exit(if ok { 0 } else { 1 }) }
+            }
+            pid => {
+                let mut status = 0;
+                assert_eq!(unsafe { libc::waitpid(pid, &mut status, 0) }, pid);
+                assert!(libc::WIFEXITED(status), "killed by signal {}", libc::WTERMSIG(status));
+                assert_eq!(libc::WEXITSTATUS(status), 0);
+            }
+        }
+    }
+
+    #[test]
+    fn no_signal() {
+        block().unwrap();
+        assert!(!wait(Duration::from_millis(10)));
+    }
+}
@@ -0,0 +1,137 @@
+use serde_derive::{Deserialize, Serialize};
+
+use crate::config::{self, NamedLink, ParseError};
+use crate::{Node, Port};
+
+/// Everything known of a port, to find it again after a reboot
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PortIdentity {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub node: Option<String>,
+
+    pub port: String,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub alias: Option<String>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub object_path: Option<String>,
+}
+
+impl PortIdentity {
+    pub fn of(port: &Port, node: Option<&Node>) -> PortIdentity {
+        PortIdentity {
+            node: node.map(|node| node.name.clone()),
+            port: port.name.clone(),
+            alias: port.alias.clone(),
+            object_path: port.object_path.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SnapshotLink {
+    pub src: PortIdentity,
+    pub dst: PortIdentity,
+}
+
+/// All the links of the graph
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Snapshot {
+    pub links: Vec<SnapshotLink>,
+}
+
+impl Snapshot {
+    pub fn load(filename: &str) -> Result<Snapshot, config::Error> {
+        let contents = std::fs::read_to_string(filename)?;
+        serde_yaml::from_str(&contents).map_err(|error| {
+            config::Error::ParseError(ParseError {
+                filename: filename.to_string(),
+                message: error.to_string(),
+            })
+        })
+    }
+
+    pub fn save(&self, filename: &str) -> Result<(), config::Error> {
+        config::write_file(filename, &serde_yaml::to_string(self).expect("Failed to serialize yaml"))
+    }
+}
+
+/// The port among the candidates the identity refers to: the one with the same object path, or
+/// else with the same node and port names, or else with the same alias
+pub fn find(identity: &PortIdentity, candidates: &[PortIdentity]) -> Option<usize> {
+    let same_path = |candidate: &PortIdentity| identity.object_path.is_some() && candidate.object_path == identity.object_path;
+    let same_names = |candidate: &PortIdentity| {
+        identity.node.is_some() && candidate.node == identity.node && candidate.port == identity.port
+    };
+    let same_alias = |candidate: &PortIdentity| identity.alias.is_some() && candidate.alias == identity.alias;
+    candidates
+        .iter()
+        .position(same_path)
+        .or_else(|| candidates.iter().position(same_names))
+        .or_else(|| candidates.iter().position(same_alias))
+}
+
+/// Progress of snapshot restore
+#[derive(Debug, Clone)]
+pub struct Restore {
+    pub snapshot: Snapshot,
+    /// Whether to remove the links that aren't in the snapshot
+    pub prune: bool,
+    /// The links whose ports weren't found
+    pub missing: usize,
+    pub pruned: Vec<NamedLink>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn identity(node: &str, port: &str, alias: Option<&str>, object_path: Option<&str>) -> PortIdentity {
+        PortIdentity {
+            node: Some(node.to_string()),
+            port: port.to_string(),
+            alias: alias.map(str::to_string),
+            object_path: object_path.map(str::to_string),
+        }
+    }
+
+    #[test]
+    fn identities() {
+        let candidates = [
+            identity("alsa_output.usb", "playback_FL", Some("USB Audio:playback_FL"), Some("alsa:pcm:2:front:2:playback:playback_0")),
+            identity("alsa_output.pci", "playback_FL", Some("ALC257 Analog:playback_FL"), Some("alsa:pcm:0:front:0:playback:playback_0")),
+            identity("Firefox", "output_FL", None, None),
+        ];
+        // the card numbers changed after a reboot, but the object path wins over the names
+        let wanted = identity("alsa_output.pci", "playback_FL", None, Some("alsa:pcm:2:front:2:playback:playback_0"));
+        assert_eq!(find(&wanted, &candidates), Some(0));
+        let wanted = identity("alsa_output.pci", "playback_FL", None, Some("alsa:pcm:7:front:7:playback:playback_0"));
+        assert_eq!(find(&wanted, &candidates), Some(1));
+        let wanted = identity("gone", "gone", Some("ALC257 Analog:playback_FL"), None);
+        assert_eq!(find(&wanted, &candidates), Some(1));
+        assert_eq!(find(&identity("Firefox", "output_FR", None, None), &candidates), None);
+
+        let snapshot = Snapshot {
+            links: vec![SnapshotLink {
+                src: candidates[2].clone(),
+                dst: candidates[1].clone(),
+            }],
+        };
+        let yaml = serde_yaml::to_string(&snapshot).unwrap();
+        assert_eq!(
+            yaml,
+            "links:
+- src:
+    node: Firefox
+    port: output_FL
+  dst:
+    node: alsa_output.pci
+    port: playback_FL
+    alias: ALC257 Analog:playback_FL
+    object_path: alsa:pcm:0:front:0:playback:playback_0
+"
+        );
+        assert_eq!(serde_yaml::from_str::<Snapshot>(&yaml).unwrap(), snapshot);
+    }
+}
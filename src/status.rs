@@ -0,0 +1,141 @@
+use serde_derive::{Deserialize, Serialize};
+use std::fmt;
+use std::time::{Duration, Instant};
+
+use crate::config::NamedLink;
+
+/// State of one configured link, as seen by the daemon
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum LinkState {
+    Linked,
+    WaitingForSrc,
+    WaitingForDst,
+    Failed(String),
+    Pending,
+}
+
+impl fmt::Display for LinkState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LinkState::Linked => write!(f, "linked"),
+            LinkState::WaitingForSrc => write!(f, "waiting for src"),
+            LinkState::WaitingForDst => write!(f, "waiting for dst"),
+            LinkState::Failed(_) => write!(f, "failed"),
+            LinkState::Pending => write!(f, "pending"),
+        }
+    }
+}
+
+/// Bookkeeping the daemon keeps for each configured link
+#[derive(Debug)]
+pub struct LinkStatus {
+    pub state: LinkState,
+    pub since: Instant,
+    pub relinks: u32,
+    linked_before: bool,
+}
+
+impl Default for LinkStatus {
+    fn default() -> Self {
+        LinkStatus {
+            state: LinkState::Pending,
+            since: Instant::now(),
+            relinks: 0,
+            linked_before: false,
+        }
+    }
+}
+
+impl LinkStatus {
+    /// Changes the state; the time spent in the state is reset only when it actually changes
+    pub fn set(&mut self, state: LinkState) {
+        if state == LinkState::Pending && self.state != LinkState::Pending && self.linked_before {
+            self.relinks += 1;
+        }
+        if state == LinkState::Linked {
+            self.linked_before = true;
+        }
+        if state != self.state {
+            self.state = state;
+            self.since = Instant::now();
+        }
+    }
+}
+
+/// One row of the status report, as sent over the control socket
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct StatusEntry {
+    pub link: NamedLink,
+    pub state: LinkState,
+    pub duration_secs: u64,
+    pub relinks: u32,
+}
+
+impl StatusEntry {
+    pub fn new(link: &NamedLink, status: &LinkStatus) -> Self {
+        StatusEntry {
+            link: link.clone(),
+            state: status.state.clone(),
+            duration_secs: status.since.elapsed().as_secs(),
+            relinks: status.relinks,
+        }
+    }
+}
+
+impl fmt::Display for StatusEntry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:<16} {:>9}  relinks {:<3} \"{}\" -> \"{}\"",
+            self.state.to_string(),
+            format_duration(Duration::from_secs(self.duration_secs)),
+            self.relinks,
+            self.link.src.0,
+            self.link.dst.0
+        )?;
+        if let LinkState::Failed(error) = &self.state {
+            write!(f, ": {error}")?;
+        }
+        Ok(())
+    }
+}
+
+fn format_duration(duration: Duration) -> String {
+    let secs = duration.as_secs();
+    let (hours, mins, secs) = (secs / 3600, secs / 60 % 60, secs % 60);
+    if hours > 0 {
+        format!("{hours}h{mins:02}m{secs:02}s")
+    } else if mins > 0 {
+        format!("{mins}m{secs:02}s")
+    } else {
+        format!("{secs}s")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn relinks() {
+        let mut status = LinkStatus::default();
+        status.set(LinkState::WaitingForDst);
+        status.set(LinkState::Pending);
+        assert_eq!(status.relinks, 0);
+        status.set(LinkState::Linked);
+        let since = status.since;
+        status.set(LinkState::Linked);
+        assert_eq!(status.since, since);
+        status.set(LinkState::Pending);
+        status.set(LinkState::Pending);
+        assert_eq!((status.state.clone(), status.relinks), (LinkState::Pending, 1));
+    }
+
+    #[test]
+    fn durations() {
+        assert_eq!(format_duration(Duration::from_secs(7)), "7s");
+        assert_eq!(format_duration(Duration::from_secs(65)), "1m05s");
+        assert_eq!(format_duration(Duration::from_secs(3 * 3600 + 4)), "3h00m04s");
+    }
+}
@@ -0,0 +1,340 @@
+use std::collections::HashSet;
+use std::fs::OpenOptions;
+use std::io;
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEventKind, KeyModifiers};
+use ratatui::layout::{Constraint, Layout};
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::text::{Line, Span};
+use ratatui::widgets::{Block, Borders, List, ListItem, ListState, Paragraph};
+use ratatui::{DefaultTerminal, Frame};
+use tracing::error;
+
+use crate::config::{NamedLink, PortName};
+use crate::control::{Request, Response};
+use crate::dot::Edge;
+use crate::{Main, PortDirection};
+
+const HELP: &str = "Tab: switch pane  ↑↓: move  Space: toggle link  s: save  q: quit   \
+                    [x] linked  [+] configured, not linked  [-] linked, not configured";
+
+pub fn default_log_path() -> PathBuf {
+    crate::control::default_socket_path().with_file_name("pw-connections-tui.log")
+}
+
+/// The ports and links shown
+#[derive(Debug, Default)]
+struct View {
+    outputs: Vec<PortName>,
+    inputs: Vec<PortName>,
+    linked: HashSet<(PortName, PortName)>,
+    configured: HashSet<(PortName, PortName)>,
+}
+
+impl View {
+    fn new(main: &Main) -> View {
+        let mut view = View::default();
+        for port in main.ports.values() {
+            match port.port_direction {
+                PortDirection::Out => view.outputs.push(port.port_name.clone()),
+                PortDirection::In => view.inputs.push(port.port_name.clone()),
+            }
+        }
+        for ports in [&mut view.outputs, &mut view.inputs] {
+            ports.sort();
+            ports.dedup();
+        }
+        view.linked = main.graph_links().into_iter().map(|link| (link.src, link.dst)).collect();
+        view.configured = main.config_links.0.iter().map(|link| (link.src.clone(), link.dst.clone())).collect();
+        view
+    }
+
+    fn edge(&self, src: &PortName, dst: &PortName) -> Option<Edge> {
+        let key = (src.clone(), dst.clone());
+        match (self.linked.contains(&key), self.configured.contains(&key)) {
+            (true, true) => Some(Edge::Managed),
+            (true, false) => Some(Edge::Unmanaged),
+            (false, true) => Some(Edge::Missing),
+            (false, false) => None,
+        }
+    }
+
+    fn link_count(&self, src: &PortName) -> usize {
+        self.linked.iter().filter(|(linked_src, _)| linked_src == src).count()
+    }
+}
+
+/// The requests that turn a link on or off; the names are those of the graph, not patterns
+fn toggle(edge: Option<Edge>, link: NamedLink) -> Vec<Request> {
+    match edge {
+        None => vec![Request::AddExactLink(link)],
+        Some(Edge::Managed | Edge::Missing) => vec![Request::RemoveExactLink(link)],
+        // taken over first, so that removing it unlinks it
+        Some(Edge::Unmanaged) => vec![Request::AddExactLink(link.clone()), Request::RemoveExactLink(link)],
+    }
+}
+
+fn mark(edge: Option<Edge>) -> Span<'static> {
+    match edge {
+        Some(Edge::Managed) => Span::styled("[x] ", Style::new().fg(Color::Green)),
+        Some(Edge::Missing) => Span::styled("[+] ", Style::new().fg(Color::Red)),
+        Some(Edge::Unmanaged) => Span::styled("[-] ", Style::new().fg(Color::Yellow)),
+        None => Span::raw("[ ] "),
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Pane {
+    Outputs,
+    Inputs,
+}
+
+struct State {
+    pane: Pane,
+    outputs: ListState,
+    inputs: ListState,
+    message: String,
+}
+
+impl State {
+    fn selected<'a>(&self, view: &'a View) -> (Option<&'a PortName>, Option<&'a PortName>) {
+        (
+            self.outputs.selected().and_then(|index| view.outputs.get(index)),
+            self.inputs.selected().and_then(|index| view.inputs.get(index)),
+        )
+    }
+
+    // Keeps the selections within the lists, which change with the graph
+    fn clamp(&mut self, view: &View) {
+        for (list, len) in [(&mut self.outputs, view.outputs.len()), (&mut self.inputs, view.inputs.len())] {
+            list.select(match len {
+                0 => None,
+                len => Some(list.selected().unwrap_or(0).min(len - 1)),
+            });
+        }
+    }
+}
+
+fn draw(frame: &mut Frame, view: &View, state: &mut State) {
+    let [panes, status] = Layout::vertical([Constraint::Min(3), Constraint::Length(2)]).areas(frame.area());
+    let [left, right] = Layout::horizontal([Constraint::Percentage(50); 2]).areas(panes);
+    let block = |title, pane| {
+        let style = if state.pane == pane { Style::new().fg(Color::Cyan) } else { Style::new() };
+        Block::new().borders(Borders::ALL).border_style(style).title(title)
+    };
+    let highlight = Style::new().add_modifier(Modifier::REVERSED);
+
+    let outputs: Vec<ListItem> = view
+        .outputs
+        .iter()
+        .map(|src| ListItem::new(format!("{} ({})", src.0, view.link_count(src))))
+        .collect();
+    let outputs = List::new(outputs).block(block("Outputs", Pane::Outputs)).highlight_style(highlight);
+    frame.render_stateful_widget(outputs, left, &mut state.outputs);
+
+    let (src, _) = state.selected(view);
+    let inputs: Vec<ListItem> = view
+        .inputs
+        .iter()
+        .map(|dst| {
+            let edge = src.and_then(|src| view.edge(src, dst));
+            ListItem::new(Line::from(vec![mark(edge), Span::raw(dst.0.clone())]))
+        })
+        .collect();
+    let title = match src {
+        Some(src) => format!("Inputs, linked to {}", src.0),
+        None => "Inputs".to_string(),
+    };
+    let inputs = List::new(inputs).block(block(&title, Pane::Inputs)).highlight_style(highlight);
+    frame.render_stateful_widget(inputs, right, &mut state.inputs);
+
+    frame.render_widget(Paragraph::new(vec![Line::raw(state.message.as_str()), Line::raw(HELP)]), status);
+}
+
+fn request(main: &Mutex<Main>, request: Request) -> Result<(), String> {
+    match main.lock().expect("Failed to lock main").handle_request(request) {
+        Response::Error(error) => Err(error),
+        _ => Ok(()),
+    }
+}
+
+// Handles a key; returns whether to quit
+fn key(main: &Mutex<Main>, view: &View, state: &mut State, code: KeyCode, modifiers: KeyModifiers) -> bool {
+    let list = match state.pane {
+        Pane::Outputs => &mut state.outputs,
+        Pane::Inputs => &mut state.inputs,
+    };
+    match code {
+        KeyCode::Char('q') | KeyCode::Esc => return true,
+        KeyCode::Char('c') if modifiers.contains(KeyModifiers::CONTROL) => return true,
+        KeyCode::Tab | KeyCode::BackTab | KeyCode::Left | KeyCode::Right => {
+            state.pane = match state.pane {
+                Pane::Outputs => Pane::Inputs,
+                Pane::Inputs => Pane::Outputs,
+            }
+        }
+        KeyCode::Up | KeyCode::Char('k') => list.select_previous(),
+        KeyCode::Down | KeyCode::Char('j') => list.select_next(),
+        KeyCode::Home => list.select_first(),
+        KeyCode::End => list.select_last(),
+        KeyCode::Char(' ') | KeyCode::Enter => {
+            if let (Some(src), Some(dst)) = state.selected(view) {
+                let link = NamedLink::new(&src.0, &dst.0);
+                let result = toggle(view.edge(src, dst), link).into_iter().try_for_each(|request_| request(main, request_));
+                state.message = match result {
+                    Ok(()) => format!("Toggled \"{}\" -> \"{}\"", src.0, dst.0),
+                    Err(error) => error,
+                };
+            }
+        }
+        KeyCode::Char('s') => {
+            state.message = match request(main, Request::Save) {
+                Ok(()) => "Saved the links into the config file".to_string(),
+                Err(error) => error,
+            };
+        }
+        _ => (),
+    }
+    false
+}
+
+fn run(terminal: &mut DefaultTerminal, main: &Mutex<Main>, stop: &AtomicBool, log: &Path) -> io::Result<()> {
+    let mut state = State {
+        pane: Pane::Outputs,
+        outputs: ListState::default(),
+        inputs: ListState::default(),
+        message: format!("Messages go to {}", log.display()),
+    };
+    while !stop.load(Ordering::Relaxed) {
+        let view = View::new(&main.lock().expect("Failed to lock main"));
+        state.clamp(&view);
+        terminal.draw(|frame| draw(frame, &view, &mut state))?;
+        // redraw now and then for the changes of the graph
+        if !event::poll(Duration::from_millis(200))? {
+            continue;
+        }
+        if let Event::Key(key_event) = event::read()? {
+            if key_event.kind == KeyEventKind::Press && key(main, &view, &mut state, key_event.code, key_event.modifiers) {
+                main.lock().expect("Failed to lock main").shutdown_requested = true;
+                break;
+            }
+        }
+    }
+    Ok(())
+}
+
+// Sends the standard error to the file, so that the messages don't scribble over the screen;
+// returns the previous standard error
+fn redirect_stderr(path: &Path) -> io::Result<OwnedFd> {
+    let file = OpenOptions::new().create(true).append(true).open(path)?;
+    let saved = unsafe { libc::dup(2) };
+    if saved < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    let saved = unsafe { OwnedFd::from_raw_fd(saved) };
+    if unsafe { libc::dup2(file.as_raw_fd(), 2) } < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(saved)
+}
+
+/// The terminal UI; the terminal is given back when this is dropped
+pub struct Tui {
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+    stderr: OwnedFd,
+}
+
+impl Drop for Tui {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(thread) = self.thread.take() {
+            let _ignore = thread.join();
+        }
+        unsafe { libc::dup2(self.stderr.as_raw_fd(), 2) };
+    }
+}
+
+pub fn start(main: Arc<Mutex<Main>>, log: &Path) -> io::Result<Tui> {
+    let stderr = redirect_stderr(log)?;
+    let mut terminal = match ratatui::try_init() {
+        Ok(terminal) => terminal,
+        Err(error) => {
+            unsafe { libc::dup2(stderr.as_raw_fd(), 2) };
+            return Err(error);
+        }
+    };
+    let stop = Arc::new(AtomicBool::new(false));
+    let thread = thread::spawn({
+        let stop = stop.clone();
+        let log = log.to_path_buf();
+        move || {
+            let result = run(&mut terminal, &main, &stop, &log);
+            ratatui::restore();
+            if let Err(error) = result {
+                error!(%error, "Terminal UI error");
+                main.lock().expect("Failed to lock main").shutdown_requested = true;
+            }
+        }
+    });
+    Ok(Tui {
+        stop,
+        thread: Some(thread),
+        stderr,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{Config, ConfigFormat};
+    use crate::Mode;
+
+    fn name(name: &str) -> PortName {
+        PortName(name.to_string())
+    }
+
+    #[test]
+    fn toggling() {
+        let (synth, speaker, recorder) = (name("synth:out"), name("speaker:in"), name("recorder:in"));
+        let view = View {
+            outputs: vec![synth.clone()],
+            inputs: vec![recorder.clone(), speaker.clone()],
+            linked: HashSet::from([(synth.clone(), speaker.clone()), (synth.clone(), recorder.clone())]),
+            configured: HashSet::from([(synth.clone(), speaker.clone())]),
+        };
+        assert_eq!(view.edge(&synth, &speaker), Some(Edge::Managed));
+        assert_eq!(view.edge(&synth, &recorder), Some(Edge::Unmanaged));
+        assert_eq!(view.edge(&recorder, &synth), None);
+        assert_eq!(view.link_count(&synth), 2);
+
+        let link = NamedLink::new(&synth.0, &recorder.0);
+        assert!(matches!(toggle(None, link.clone()).as_slice(), [Request::AddExactLink(_)]));
+        assert!(matches!(toggle(Some(Edge::Missing), link.clone()).as_slice(), [Request::RemoveExactLink(_)]));
+        assert!(matches!(
+            toggle(Some(Edge::Unmanaged), link).as_slice(),
+            [Request::AddExactLink(_), Request::RemoveExactLink(_)]
+        ));
+    }
+
+    #[test]
+    fn names_with_braces() {
+        let config = Config::deserialize("links: []\n", "tui.yaml", ConfigFormat::Yaml).unwrap();
+        let main = Mutex::new(Main::new(config, None, None, Mode::Daemon));
+        let link = NamedLink::new("Synth {A,B}:out", "speaker:in");
+        for toggled in toggle(None, link.clone()) {
+            assert_eq!(request(&main, toggled), Ok(()));
+        }
+        assert_eq!(main.lock().unwrap().config_links.0, vec![link.clone()]);
+        for toggled in toggle(Some(Edge::Managed), link) {
+            assert_eq!(request(&main, toggled), Ok(()));
+        }
+        assert!(main.lock().unwrap().config_links.0.is_empty());
+    }
+}